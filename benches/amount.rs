@@ -0,0 +1,93 @@
+//! Compares the fixed-point `Amount` against `rust_decimal::Decimal` for the operations the
+//! engine performs on balances, and measures how the engine reads amounts from input rows.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use rust_decimal::Decimal;
+use yet_another_transactions_processor::amount::Amount;
+use yet_another_transactions_processor::precision::Precision;
+use yet_another_transactions_processor::transaction::TransactionRecord;
+
+const INPUTS: [&str; 8] = [
+    "1.0", "0.0001", "250.5", "99.9999", "1000", "12.3456", "0.5", "7",
+];
+
+fn balance_updates(c: &mut Criterion) {
+    let amounts: Vec<Amount> = INPUTS.iter().map(|s| s.parse().unwrap()).collect();
+    let decimals: Vec<Decimal> = INPUTS.iter().map(|s| s.parse().unwrap()).collect();
+
+    let mut group = c.benchmark_group("balance_updates");
+    group.bench_function("amount", |b| {
+        b.iter(|| {
+            let mut available = Amount::ZERO;
+            let mut held = Amount::ZERO;
+            for &amount in black_box(&amounts).iter().cycle().take(1000) {
+                available = available.checked_add(amount).unwrap();
+                if available >= amount {
+                    available = available.checked_sub(amount).unwrap();
+                    held = held.checked_add(amount).unwrap();
+                }
+            }
+            (available, held)
+        });
+    });
+    group.bench_function("decimal", |b| {
+        b.iter(|| {
+            let mut available = Decimal::ZERO;
+            let mut held = Decimal::ZERO;
+            for &amount in black_box(&decimals).iter().cycle().take(1000) {
+                available = available.checked_add(amount).unwrap();
+                if available >= amount {
+                    available = available.checked_sub(amount).unwrap();
+                    held = held.checked_add(amount).unwrap();
+                }
+            }
+            (available, held)
+        });
+    });
+    group.finish();
+}
+
+/// Reading amounts from input rows. The engine deserializes them as `Decimal`, applies the
+/// precision and converts the result to an `Amount`; `amount` parses them directly instead.
+fn parsing(c: &mut Criterion) {
+    let precision = Precision::default();
+    let input: String = std::iter::once("type,client,tx,amount\n".to_string())
+        .chain(
+            INPUTS
+                .iter()
+                .enumerate()
+                .map(|(tx, amount)| format!("deposit,1,{tx},{amount}\n")),
+        )
+        .collect();
+
+    let mut group = c.benchmark_group("parsing");
+    group.bench_function("engine_rows", |b| {
+        b.iter(|| {
+            let mut reader = csv::Reader::from_reader(black_box(input.as_bytes()));
+            for record in reader.deserialize::<TransactionRecord>() {
+                black_box(record.unwrap().parsed_amount(precision).unwrap());
+            }
+        });
+    });
+    group.bench_function("engine", |b| {
+        b.iter(|| {
+            for s in black_box(INPUTS) {
+                let decimal = precision.apply(s.parse::<Decimal>().unwrap()).unwrap();
+                black_box(Amount::try_from(decimal).unwrap());
+            }
+        });
+    });
+    group.bench_function("amount", |b| {
+        b.iter(|| {
+            for s in black_box(INPUTS) {
+                black_box(s.parse::<Amount>().unwrap());
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, balance_updates, parsing);
+criterion_main!(benches);
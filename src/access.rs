@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::input::{csv_reader, records_with_lines};
+use crate::rejection::Rejection;
+use crate::transaction::ClientId;
+
+/// Which clients may transact, from a blocklist and/or an allowlist. Allows everyone by
+/// default.
+#[derive(Debug, Clone, Default)]
+pub struct ClientAccess {
+    blocked: HashSet<ClientId>,
+    /// Only these clients may transact, if given.
+    allowed: Option<HashSet<ClientId>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientListRecord {
+    client: ClientId,
+}
+
+impl ClientAccess {
+    /// Reads the lists, each a CSV file with a `client` column.
+    pub fn load(blocklist: Option<&str>, allowlist: Option<&str>) -> Result<Self> {
+        Ok(ClientAccess {
+            blocked: match blocklist {
+                Some(filename) => load_client_list(filename)?,
+                None => HashSet::new(),
+            },
+            allowed: allowlist.map(load_client_list).transpose()?,
+        })
+    }
+
+    /// Rejects clients on the blocklist or missing from the allowlist.
+    pub fn check(&self, client: ClientId) -> Result<(), Rejection> {
+        let allowed = self
+            .allowed
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&client));
+        if !allowed || self.blocked.contains(&client) {
+            return Err(Rejection::Blocked { client });
+        }
+        Ok(())
+    }
+}
+
+fn load_client_list(filename: &str) -> Result<HashSet<ClientId>> {
+    let mut reader =
+        csv_reader(filename).with_context(|| format!("failed to open client list: {filename}"))?;
+    records_with_lines::<ClientListRecord, _>(&mut reader)?
+        .map(|result| {
+            result
+                .map(|(_, record)| record.client)
+                .with_context(|| format!("failed to read client list: {filename}"))
+        })
+        .collect()
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+
+use anyhow::{Context, Result, bail};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+use crate::engine::Step;
+use crate::rejection::Rejection;
+use crate::transaction::{ClientId, TransactionId, TransactionType};
+
+/// Patterns flagged in the alerts file, read from a TOML file. Rules missing from the file
+/// keep their default settings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertRules {
+    pub quick_withdrawal: QuickWithdrawalRule,
+    pub structuring: StructuringRule,
+    pub dispute_cycles: DisputeCyclesRule,
+}
+
+/// A deposit withdrawn in full within `window_rows` rows and then disputed. Withdrawals, and
+/// transfers to other clients, are allocated to the client's recent deposits first in, first
+/// out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuickWithdrawalRule {
+    pub enabled: bool,
+    pub window_rows: u64,
+}
+
+/// At least `min_count` deposits within `window_rows` rows, each just under `threshold`: by
+/// at most `margin` of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StructuringRule {
+    pub enabled: bool,
+    pub threshold: Decimal,
+    pub margin: Decimal,
+    pub min_count: usize,
+    pub window_rows: u64,
+}
+
+/// A deposit disputed and resolved at least `min_cycles` times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputeCyclesRule {
+    pub enabled: bool,
+    pub min_cycles: u32,
+}
+
+impl Default for QuickWithdrawalRule {
+    fn default() -> Self {
+        QuickWithdrawalRule {
+            enabled: true,
+            window_rows: 10,
+        }
+    }
+}
+
+impl Default for StructuringRule {
+    /// Three deposits within 100 rows that are less than 10% under 10,000.
+    fn default() -> Self {
+        StructuringRule {
+            enabled: true,
+            threshold: Decimal::from(10_000),
+            margin: Decimal::new(1, 1),
+            min_count: 3,
+            window_rows: 100,
+        }
+    }
+}
+
+impl Default for DisputeCyclesRule {
+    fn default() -> Self {
+        DisputeCyclesRule {
+            enabled: true,
+            min_cycles: 2,
+        }
+    }
+}
+
+impl AlertRules {
+    /// Reads and checks a TOML file of alert rules.
+    pub fn load(filename: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(filename)
+            .with_context(|| format!("failed to read alert rules: {filename}"))?;
+        let rules: AlertRules =
+            toml::from_str(&content).with_context(|| format!("invalid alert rules: {filename}"))?;
+        rules
+            .check()
+            .with_context(|| format!("invalid alert rules: {filename}"))?;
+        Ok(rules)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.quick_withdrawal.window_rows == 0 {
+            bail!("quick_withdrawal: window_rows must be at least 1");
+        }
+        let structuring = &self.structuring;
+        if structuring.threshold.is_sign_negative() {
+            bail!("structuring: negative threshold");
+        }
+        if structuring.margin.is_sign_negative() || structuring.margin > Decimal::ONE {
+            bail!("structuring: margin must be between 0 and 1");
+        }
+        if structuring.min_count == 0 {
+            bail!("structuring: min_count must be at least 1");
+        }
+        if structuring.window_rows == 0 {
+            bail!("structuring: window_rows must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// A suspicious pattern found on `line`, with the transactions involved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub line: u64,
+    pub client: ClientId,
+    pub rule: &'static str,
+    pub txs: Vec<TransactionId>,
+}
+
+/// Watches processed rows for the patterns of the alert rules, without affecting processing.
+#[derive(Debug)]
+pub struct AlertMonitor {
+    rules: AlertRules,
+    clients: HashMap<ClientId, ClientActivity>,
+    alerts: Vec<Alert>,
+}
+
+#[derive(Debug, Default)]
+struct ClientActivity {
+    deposits: HashMap<TransactionId, DepositActivity>,
+    /// Recent deposits that can still be withdrawn quickly, in input order.
+    recent_deposits: VecDeque<TransactionId>,
+    /// Recent deposits just under the structuring threshold, with their lines.
+    near_threshold: VecDeque<(u64, TransactionId)>,
+}
+
+#[derive(Debug)]
+struct DepositActivity {
+    line: u64,
+    amount: Amount,
+    /// Withdrawals within the quick withdrawal window after the deposit that were allocated
+    /// to it, and the amount allocated.
+    withdrawals: Vec<TransactionId>,
+    withdrawn: Amount,
+    resolves: u32,
+    alerted: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AlertRecord {
+    line: u64,
+    client: ClientId,
+    rule: &'static str,
+    /// Space-separated ids of the transactions involved.
+    txs: String,
+}
+
+impl AlertMonitor {
+    #[must_use]
+    pub fn new(rules: AlertRules) -> Self {
+        AlertMonitor {
+            rules,
+            clients: HashMap::new(),
+            alerts: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    pub fn record(&mut self, step: &Step) {
+        let activity = self.clients.entry(step.client).or_default();
+        let applied = step.result.is_ok();
+        let alert = match (step.tx_type, step.amount) {
+            (TransactionType::Deposit, Some(amount)) if applied => {
+                activity.deposit(&self.rules, step, amount)
+            }
+            // Moving the money to another client takes it out of the account all the same.
+            (TransactionType::Withdrawal | TransactionType::Transfer, Some(amount)) if applied => {
+                activity.withdrawal(&self.rules, step, amount);
+                None
+            }
+            // A dispute the client can't cover is suspicious too, even if it's rejected.
+            (TransactionType::Dispute, _)
+                if applied || matches!(step.result, Err(Rejection::InsufficientFunds { .. })) =>
+            {
+                activity.dispute(&self.rules, step)
+            }
+            (TransactionType::Resolve, _) if applied => activity.resolve(&self.rules, step),
+            _ => None,
+        };
+        self.alerts.extend(alert);
+    }
+
+    /// Writes the alerts in the order they were raised.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for alert in &self.alerts {
+            let txs: Vec<String> = alert.txs.iter().map(|tx| tx.0.to_string()).collect();
+            writer.serialize(AlertRecord {
+                line: alert.line,
+                client: alert.client,
+                rule: alert.rule,
+                txs: txs.join(" "),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl ClientActivity {
+    fn deposit(&mut self, rules: &AlertRules, step: &Step, amount: Amount) -> Option<Alert> {
+        self.deposits.insert(
+            step.tx,
+            DepositActivity {
+                line: step.line,
+                amount,
+                withdrawals: Vec::new(),
+                withdrawn: Amount::ZERO,
+                resolves: 0,
+                alerted: false,
+            },
+        );
+        self.recent_deposits.push_back(step.tx);
+
+        let rule = &rules.structuring;
+        let amount = amount.to_decimal();
+        let near = rule
+            .threshold
+            .checked_mul(Decimal::ONE - rule.margin)
+            .is_some_and(|floor| amount >= floor && amount < rule.threshold);
+        if !rule.enabled || !near {
+            return None;
+        }
+        self.near_threshold
+            .retain(|&(line, _)| step.line - line < rule.window_rows);
+        self.near_threshold.push_back((step.line, step.tx));
+        if self.near_threshold.len() < rule.min_count {
+            return None;
+        }
+        let txs = self.near_threshold.drain(..).map(|(_, tx)| tx).collect();
+        Some(Alert {
+            line: step.line,
+            client: step.client,
+            rule: "structuring",
+            txs,
+        })
+    }
+
+    /// Allocates the withdrawal to the recent deposits first in, first out, so that each part
+    /// of it counts towards a single deposit.
+    fn withdrawal(&mut self, rules: &AlertRules, step: &Step, amount: Amount) {
+        let window_rows = rules.quick_withdrawal.window_rows;
+        let deposits = &self.deposits;
+        self.recent_deposits.retain(|tx| {
+            deposits
+                .get(tx)
+                .is_some_and(|deposit| step.line - deposit.line <= window_rows)
+        });
+        let mut remaining = amount;
+        for tx in &self.recent_deposits {
+            if remaining <= Amount::ZERO {
+                break;
+            }
+            let Some(deposit) = self.deposits.get_mut(tx) else {
+                continue;
+            };
+            // Can't overflow: both are between zero and the deposit or withdrawal amount.
+            let unallocated =
+                Amount::from_units(deposit.amount.units() - deposit.withdrawn.units());
+            let allocated = remaining.min(unallocated);
+            if allocated > Amount::ZERO {
+                deposit.withdrawals.push(step.tx);
+                deposit.withdrawn =
+                    Amount::from_units(deposit.withdrawn.units() + allocated.units());
+                remaining = Amount::from_units(remaining.units() - allocated.units());
+            }
+        }
+    }
+
+    fn dispute(&mut self, rules: &AlertRules, step: &Step) -> Option<Alert> {
+        let deposit = self.deposits.get_mut(&step.tx)?;
+        if !rules.quick_withdrawal.enabled || deposit.alerted || deposit.withdrawn < deposit.amount
+        {
+            return None;
+        }
+        deposit.alerted = true;
+        Some(Alert {
+            line: step.line,
+            client: step.client,
+            rule: "quick-withdrawal-dispute",
+            txs: std::iter::once(step.tx)
+                .chain(deposit.withdrawals.iter().copied())
+                .collect(),
+        })
+    }
+
+    fn resolve(&mut self, rules: &AlertRules, step: &Step) -> Option<Alert> {
+        let deposit = self.deposits.get_mut(&step.tx)?;
+        deposit.resolves += 1;
+        (rules.dispute_cycles.enabled && deposit.resolves == rules.dispute_cycles.min_cycles).then(
+            || Alert {
+                line: step.line,
+                client: step.client,
+                rule: "dispute-cycles",
+                txs: vec![step.tx],
+            },
+        )
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::ops::Neg;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Fixed-point monetary amount stored as a whole number of ten-thousandths.
+///
+/// Balance updates only ever add, subtract and compare amounts, which is much cheaper on a
+/// plain integer than on a 128-bit [`Decimal`]. Conversions from and to [`Decimal`] are exact.
+/// The range is symmetric, without `i64::MIN`, so that negating an amount can't overflow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i64);
+
+impl Amount {
+    /// Number of decimal places an amount can hold.
+    pub const SCALE: u32 = 4;
+    pub const ZERO: Amount = Amount(0);
+
+    const UNITS_PER_WHOLE: i64 = 10_i64.pow(Self::SCALE);
+
+    #[must_use]
+    pub const fn from_units(units: i64) -> Self {
+        Amount(units)
+    }
+
+    #[must_use]
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    #[must_use]
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).and_then(Self::in_range)
+    }
+
+    #[must_use]
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).and_then(Self::in_range)
+    }
+
+    fn in_range(units: i64) -> Option<Amount> {
+        (units != i64::MIN).then_some(Amount(units))
+    }
+
+    #[must_use]
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    #[must_use]
+    pub fn to_decimal(self) -> Decimal {
+        Decimal::new(self.0, Self::SCALE)
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount(-self.0)
+    }
+}
+
+impl TryFrom<Decimal> for Amount {
+    type Error = anyhow::Error;
+
+    /// Converts without rounding, failing if the value has more than [`Amount::SCALE`]
+    /// decimal places or is out of range.
+    fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+        let value = value.normalize();
+        let scale = value.scale();
+        if scale > Self::SCALE {
+            bail!(
+                "amount {value} has more than {} decimal places",
+                Self::SCALE
+            );
+        }
+        10_i128
+            .checked_pow(Self::SCALE - scale)
+            .and_then(|factor| value.mantissa().checked_mul(factor))
+            .and_then(|units| i64::try_from(units).ok())
+            .and_then(Self::in_range)
+            .ok_or_else(|| anyhow!("amount out of range: {value}"))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = anyhow::Error;
+
+    /// Parses a plain decimal number such as `-12.5` exactly, without going through a float.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+            bail!("invalid amount: {s}");
+        }
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > Self::SCALE as usize {
+            bail!("amount {s} has more than {} decimal places", Self::SCALE);
+        }
+
+        let out_of_range = || anyhow!("amount out of range: {s}");
+        let whole: i64 = whole.parse().map_err(|_| out_of_range())?;
+        let fraction = fraction
+            .bytes()
+            .chain(std::iter::repeat(b'0'))
+            .take(Self::SCALE as usize)
+            .fold(0, |units, digit| units * 10 + i64::from(digit - b'0'));
+        let units = whole
+            .checked_mul(Self::UNITS_PER_WHOLE)
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or_else(out_of_range)?;
+        Ok(Amount(if negative { -units } else { units }))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let per_whole = Self::UNITS_PER_WHOLE.unsigned_abs();
+        write!(
+            f,
+            "{sign}{}.{:0width$}",
+            units / per_whole,
+            units % per_whole,
+            width = Self::SCALE as usize
+        )
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
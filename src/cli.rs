@@ -0,0 +1,416 @@
+use anyhow::{Context, Result, anyhow, bail};
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use yet_another_transactions_processor::access::ClientAccess;
+use yet_another_transactions_processor::alerts::AlertRules;
+use yet_another_transactions_processor::consolidated::{ConsolidationOptions, Rates};
+use yet_another_transactions_processor::fees::FeeSchedule;
+use yet_another_transactions_processor::limits::{Limits, OverdraftLimits, load_overdraft_limits};
+use yet_another_transactions_processor::point_in_time::{AsOf, SnapshotInterval};
+use yet_another_transactions_processor::policy::{DisputeShortfall, Policy};
+use yet_another_transactions_processor::precision::{
+    self, DEFAULT_MAX_SCALE, Precision, PrecisionMode,
+};
+use yet_another_transactions_processor::risk::RiskThresholds;
+use yet_another_transactions_processor::transaction::{ClientId, Currency};
+use yet_another_transactions_processor::velocity::VelocityLimits;
+
+const DEFAULT_EXAMPLES: usize = 5;
+
+#[derive(Debug)]
+pub enum Command {
+    /// Process the input and print the resulting client balances.
+    Process(ProcessOptions),
+    /// Check the input for problems without producing balances.
+    Validate {
+        input: String,
+        examples: usize,
+        max_scale: u32,
+    },
+    /// Compare two accounts files.
+    Diff {
+        left: String,
+        right: String,
+        tolerance: Decimal,
+    },
+    /// Process the input under its policy and an alternative one and compare the results.
+    WhatIf {
+        options: ProcessOptions,
+        alternative: Policy,
+    },
+    /// Process the input and print every transaction of a single client.
+    History {
+        client: ClientId,
+        options: ProcessOptions,
+    },
+    /// Process the input and describe how a single client ended up in its final state.
+    Explain {
+        client: ClientId,
+        options: ProcessOptions,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    pub input: String,
+    pub precision: Precision,
+    pub limits: Limits,
+    pub policy: Policy,
+    pub fees: Option<FeeSchedule>,
+    pub overdrafts: Option<OverdraftLimits>,
+    pub velocity: Option<VelocityLimits>,
+    pub access: ClientAccess,
+    pub opening_balances: Option<String>,
+    pub open_deposits: Option<String>,
+    pub as_of: Option<AsOf>,
+    pub outputs: OutputOptions,
+}
+
+/// Files and checks produced alongside the balances.
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    pub events: Option<String>,
+    pub snapshots: Option<(String, SnapshotInterval)>,
+    pub open_disputes: Option<String>,
+    pub open_dispute_totals: Option<String>,
+    pub risk_report: Option<(String, RiskThresholds)>,
+    pub verify: bool,
+    pub journal: Option<String>,
+    pub metadata: Option<String>,
+    pub alerts: Option<(String, AlertRules)>,
+    pub consolidated: Option<(String, ConsolidationOptions)>,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("validate") => {
+            args.next();
+            parse_validate(args)
+        }
+        Some("diff") => {
+            args.next();
+            parse_diff(args)
+        }
+        Some("what-if") => {
+            args.next();
+            parse_what_if(args)
+        }
+        Some("history") => {
+            args.next();
+            let (client, options) = parse_client_command(args)?;
+            Ok(Command::History { client, options })
+        }
+        Some("explain") => {
+            args.next();
+            let (client, options) = parse_client_command(args)?;
+            Ok(Command::Explain { client, options })
+        }
+        _ => Ok(Command::Process(parse_process(args)?)),
+    }
+}
+
+/// Parses the `--client` option of per-client commands and the usual process options.
+fn parse_client_command(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(ClientId, ProcessOptions)> {
+    let mut client = None;
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--client" {
+            client = Some(ClientId(parse_value(&arg, args.next())?));
+        } else {
+            rest.push(arg);
+        }
+    }
+    let client = client.context("missing --client")?;
+    Ok((client, parse_process(rest.into_iter())?))
+}
+
+/// Parses the alternative policy, given as a file and/or rule changes, and the usual process
+/// options.
+fn parse_what_if(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut alternative_file = None;
+    let mut changes = Vec::new();
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--alternative-policy" => alternative_file = Some(required_value(&arg, args.next())?),
+            "--allow-disputes-on-locked" | "--forbid-negative-available" => changes.push(arg),
+            _ => rest.push(arg),
+        }
+    }
+    if alternative_file.is_none() && changes.is_empty() {
+        bail!("what-if needs --alternative-policy or at least one policy change");
+    }
+    let options = parse_process(rest.into_iter())?;
+    if options.input == "-" {
+        bail!("what-if reads the input twice and can't read it from stdin");
+    }
+    let mut alternative = match alternative_file {
+        Some(filename) => Policy::load(&filename)?,
+        None => options.policy,
+    };
+    for change in changes {
+        match change.as_str() {
+            "--allow-disputes-on-locked" => alternative.locked_rejects_disputes = false,
+            _ => alternative.dispute_shortfall = DisputeShortfall::Reject,
+        }
+    }
+    Ok(Command::WhatIf {
+        options,
+        alternative,
+    })
+}
+
+fn parse_process(mut args: impl Iterator<Item = String>) -> Result<ProcessOptions> {
+    let mut input = None;
+    let mut max_scale = DEFAULT_MAX_SCALE;
+    let mut round = false;
+    let mut rounding = RoundingStrategy::MidpointNearestEven;
+    let mut limits = Limits::default();
+    let mut opening_balances = None;
+    let mut open_deposits = None;
+    let mut as_of = None;
+    let mut policy = None;
+    let mut fees = None;
+    let mut overdrafts = None;
+    let mut velocity = None;
+    let mut blocklist = None;
+    let mut allowlist = None;
+    let mut outputs = OutputArgs::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--opening-balances" => opening_balances = Some(required_value(&arg, args.next())?),
+            "--open-deposits" => open_deposits = Some(required_value(&arg, args.next())?),
+            "--as-of" => as_of = Some(parse_value(&arg, args.next())?),
+            "--policy" => policy = Some(required_value(&arg, args.next())?),
+            "--fees" => fees = Some(FeeSchedule::load(&required_value(&arg, args.next())?)?),
+            "--overdraft-limits" => {
+                let filename = required_value(&arg, args.next())?;
+                overdrafts = Some(load_overdraft_limits(&filename)?);
+            }
+            "--blocklist" => blocklist = Some(required_value(&arg, args.next())?),
+            "--allowlist" => allowlist = Some(required_value(&arg, args.next())?),
+            "--velocity-limits" => {
+                velocity = Some(VelocityLimits::load(&required_value(&arg, args.next())?)?);
+            }
+            "--max-amount" => limits.max_amount = Some(parse_value(&arg, args.next())?),
+            "--max-balance" => limits.max_balance = Some(parse_value(&arg, args.next())?),
+            "--max-scale" => max_scale = parse_value(&arg, args.next())?,
+            "--excess-precision" => {
+                round = match required_value(&arg, args.next())?.as_str() {
+                    "reject" => false,
+                    "round" => true,
+                    other => bail!("invalid value for {arg}: {other}"),
+                }
+            }
+            "--rounding" => {
+                rounding = precision::parse_rounding_strategy(&required_value(&arg, args.next())?)?;
+            }
+            _ if outputs.parse(&arg, &mut args)? => {}
+            _ => set_input(&mut input, arg)?,
+        }
+    }
+
+    if open_deposits.is_some() && opening_balances.is_none() {
+        bail!("--open-deposits requires --opening-balances");
+    }
+    let outputs = outputs.finish()?;
+    let policy = match policy {
+        Some(filename) => Policy::load(&filename)?,
+        None => Policy::default(),
+    };
+
+    let mode = if round {
+        PrecisionMode::Round(rounding)
+    } else {
+        PrecisionMode::Reject
+    };
+    Ok(ProcessOptions {
+        input: input.context("no input file specified")?,
+        precision: Precision::new(max_scale, mode)?,
+        limits,
+        policy,
+        fees,
+        overdrafts,
+        velocity,
+        access: ClientAccess::load(blocklist.as_deref(), allowlist.as_deref())?,
+        opening_balances,
+        open_deposits,
+        as_of,
+        outputs,
+    })
+}
+
+/// Output options as given on the command line, before checking they go together.
+#[derive(Default)]
+struct OutputArgs {
+    options: OutputOptions,
+    snapshots: Option<String>,
+    snapshot_every: Option<SnapshotInterval>,
+    risk_report: Option<String>,
+    thresholds: RiskThresholds,
+    custom_thresholds: bool,
+    alerts: Option<String>,
+    alert_rules: Option<String>,
+    consolidated: Option<String>,
+    rates: Option<String>,
+    reporting_currency: Option<Currency>,
+    consolidated_rounding: Option<RoundingStrategy>,
+}
+
+impl OutputArgs {
+    /// Takes `arg` and its value if it's an output option, returning whether it was one.
+    fn parse(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> Result<bool> {
+        let options = &mut self.options;
+        match arg {
+            "--events" => options.events = Some(required_value(arg, args.next())?),
+            "--snapshots" => self.snapshots = Some(required_value(arg, args.next())?),
+            "--snapshot-every" => self.snapshot_every = Some(parse_value(arg, args.next())?),
+            "--open-disputes" => options.open_disputes = Some(required_value(arg, args.next())?),
+            "--open-dispute-totals" => {
+                options.open_dispute_totals = Some(required_value(arg, args.next())?);
+            }
+            "--risk-report" => self.risk_report = Some(required_value(arg, args.next())?),
+            "--dispute-ratio-threshold" => {
+                self.thresholds.dispute_ratio = parse_value(arg, args.next())?;
+                self.custom_thresholds = true;
+            }
+            "--chargeback-ratio-threshold" => {
+                self.thresholds.chargeback_ratio = parse_value(arg, args.next())?;
+                self.custom_thresholds = true;
+            }
+            "--verify" => options.verify = true,
+            "--journal" => options.journal = Some(required_value(arg, args.next())?),
+            "--metadata" => options.metadata = Some(required_value(arg, args.next())?),
+            "--alerts" => self.alerts = Some(required_value(arg, args.next())?),
+            "--alert-rules" => self.alert_rules = Some(required_value(arg, args.next())?),
+            "--consolidated" => self.consolidated = Some(required_value(arg, args.next())?),
+            "--rates" => self.rates = Some(required_value(arg, args.next())?),
+            "--reporting-currency" => {
+                self.reporting_currency = Some(parse_value(arg, args.next())?);
+            }
+            "--consolidated-rounding" => {
+                let strategy =
+                    precision::parse_rounding_strategy(&required_value(arg, args.next())?)?;
+                self.consolidated_rounding = Some(strategy);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn finish(self) -> Result<OutputOptions> {
+        if self.custom_thresholds && self.risk_report.is_none() {
+            bail!("ratio thresholds require --risk-report");
+        }
+        let snapshots = match (self.snapshots, self.snapshot_every) {
+            (Some(filename), Some(interval)) => Some((filename, interval)),
+            (None, None) => None,
+            (Some(_), None) => bail!("--snapshots requires --snapshot-every"),
+            (None, Some(_)) => bail!("--snapshot-every requires --snapshots"),
+        };
+        let alerts = match (self.alerts, self.alert_rules) {
+            (Some(filename), Some(rules)) => Some((filename, AlertRules::load(&rules)?)),
+            (Some(filename), None) => Some((filename, AlertRules::default())),
+            (None, Some(_)) => bail!("--alert-rules requires --alerts"),
+            (None, None) => None,
+        };
+        let consolidated = match (self.consolidated, self.rates, self.reporting_currency) {
+            (Some(filename), Some(rates), Some(currency)) => {
+                let options = ConsolidationOptions {
+                    rates: Rates::load(&rates)?,
+                    currency,
+                    rounding: self
+                        .consolidated_rounding
+                        .unwrap_or(RoundingStrategy::MidpointNearestEven),
+                };
+                Some((filename, options))
+            }
+            (Some(_), None, _) => bail!("--consolidated requires --rates"),
+            (Some(_), _, None) => bail!("--consolidated requires --reporting-currency"),
+            (None, None, None) if self.consolidated_rounding.is_none() => None,
+            (None, ..) => bail!(
+                "--rates, --reporting-currency and --consolidated-rounding require --consolidated"
+            ),
+        };
+        let thresholds = self.thresholds;
+        Ok(OutputOptions {
+            snapshots,
+            risk_report: self.risk_report.map(|filename| (filename, thresholds)),
+            alerts,
+            consolidated,
+            ..self.options
+        })
+    }
+}
+
+fn parse_validate(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut input = None;
+    let mut examples = DEFAULT_EXAMPLES;
+    let mut max_scale = DEFAULT_MAX_SCALE;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--examples" => examples = parse_value(&arg, args.next())?,
+            "--max-scale" => max_scale = parse_value(&arg, args.next())?,
+            _ => set_input(&mut input, arg)?,
+        }
+    }
+    let input = input.context("no input file specified")?;
+    Ok(Command::Validate {
+        input,
+        examples,
+        max_scale,
+    })
+}
+
+fn parse_diff(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut files = Vec::new();
+    let mut tolerance = Decimal::ZERO;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tolerance" => tolerance = parse_value(&arg, args.next())?,
+            _ if arg.starts_with("--") => bail!("unknown option: {arg}"),
+            _ => files.push(arg),
+        }
+    }
+    if tolerance.is_sign_negative() {
+        bail!("--tolerance can't be negative");
+    }
+    let [left, right] = <[String; 2]>::try_from(files)
+        .map_err(|_| anyhow!("expected two accounts files to compare"))?;
+    Ok(Command::Diff {
+        left,
+        right,
+        tolerance,
+    })
+}
+
+/// Stores the positional input argument, rejecting unknown options and extra arguments.
+fn set_input(input: &mut Option<String>, arg: String) -> Result<()> {
+    if arg.starts_with("--") {
+        bail!("unknown option: {arg}");
+    }
+    if input.is_some() {
+        bail!("unexpected argument: {arg}");
+    }
+    *input = Some(arg);
+    Ok(())
+}
+
+fn required_value(option: &str, value: Option<String>) -> Result<String> {
+    value.with_context(|| format!("missing value for {option}"))
+}
+
+fn parse_value<T>(option: &str, value: Option<String>) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    let value = required_value(option, value)?;
+    value
+        .parse()
+        .map_err(Into::into)
+        .with_context(|| format!("invalid value for {option}: {value}"))
+}
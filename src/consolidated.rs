@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use anyhow::{Context, Result, bail};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{ClientRecord, Step};
+use crate::input::{csv_reader, records_with_lines};
+use crate::transaction::{ClientId, Currency};
+
+/// Exchange rates between currencies, read from a CSV file with `from`, `to` and `rate`
+/// columns and an optional `effective` column (unix seconds).
+///
+/// A rate converts one unit of `from` into `rate` units of `to`. Rates without an effective
+/// time always apply; otherwise the latest rate effective at the time of the conversion does.
+/// A pair without a rate of its own uses the inverse of the opposite pair's.
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+    rates: HashMap<(Currency, Currency), PairRates>,
+}
+
+/// The rates of a currency pair with their effective times, sorted by effective time.
+type PairRates = Vec<(Option<u64>, Decimal)>;
+
+#[derive(Debug, Deserialize)]
+struct RateRecord {
+    from: Currency,
+    to: Currency,
+    rate: Decimal,
+    #[serde(default)]
+    effective: Option<u64>,
+}
+
+impl Rates {
+    /// Reads and checks a CSV file of rates.
+    pub fn load(filename: &str) -> Result<Self> {
+        let mut reader =
+            csv_reader(filename).with_context(|| format!("failed to open rates: {filename}"))?;
+        let mut rates = Rates::default();
+        for result in records_with_lines::<RateRecord, _>(&mut reader)? {
+            let (line, record) =
+                result.with_context(|| format!("failed to read rates: {filename}"))?;
+            rates
+                .add(&record)
+                .with_context(|| format!("invalid rate on line {line}: {filename}"))?;
+        }
+        Ok(rates)
+    }
+
+    fn add(&mut self, record: &RateRecord) -> Result<()> {
+        if record.from == record.to {
+            bail!("rate from {} to itself", record.from);
+        }
+        if record.rate <= Decimal::ZERO {
+            bail!("rate {} isn't positive", record.rate);
+        }
+        let rates = self.rates.entry((record.from, record.to)).or_default();
+        if rates
+            .iter()
+            .any(|&(effective, _)| effective == record.effective)
+        {
+            bail!("duplicate rate from {} to {}", record.from, record.to);
+        }
+        rates.push((record.effective, record.rate));
+        rates.sort_by_key(|&(effective, _)| effective);
+        Ok(())
+    }
+
+    /// The rate from `from` to `to` at time `at`, or the latest one if `at` is `None`.
+    #[must_use]
+    pub fn rate(&self, from: Currency, to: Currency, at: Option<u64>) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+        self.direct_rate(from, to, at).or_else(|| {
+            self.direct_rate(to, from, at)
+                .and_then(|rate| Decimal::ONE.checked_div(rate))
+        })
+    }
+
+    fn direct_rate(&self, from: Currency, to: Currency, at: Option<u64>) -> Option<Decimal> {
+        self.rates
+            .get(&(from, to))?
+            .iter()
+            .rev()
+            .find(|&&(effective, _)| {
+                effective.is_none_or(|effective| at.is_none_or(|at| effective <= at))
+            })
+            .map(|&(_, rate)| rate)
+    }
+}
+
+/// How the consolidated report converts balances: with which rates, into which currency and
+/// rounded how.
+#[derive(Debug, Clone)]
+pub struct ConsolidationOptions {
+    pub rates: Rates,
+    pub currency: Currency,
+    pub rounding: RoundingStrategy,
+}
+
+/// Each client's holdings across currencies converted into a reporting currency, without
+/// touching the per-currency balances.
+///
+/// Balances are converted at the rates effective at the last timestamp of the input (the
+/// latest rates if it has none) and summed at full precision. Only the sums are rounded, to
+/// the output's decimal places with the chosen strategy, and the total is the sum of the
+/// rounded available and held amounts.
+#[derive(Debug)]
+pub struct ConsolidatedReport {
+    options: ConsolidationOptions,
+    /// Latest timestamp of the processed rows.
+    as_of: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Holdings {
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ConsolidatedRecord {
+    client: ClientId,
+    currency: Currency,
+    #[serde(with = "rust_decimal::serde::str")]
+    available: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    held: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    total: Decimal,
+    /// Whether any of the client's accounts is locked.
+    locked: bool,
+}
+
+impl ConsolidatedReport {
+    #[must_use]
+    pub fn new(options: ConsolidationOptions) -> Self {
+        ConsolidatedReport {
+            options,
+            as_of: None,
+        }
+    }
+
+    pub fn record(&mut self, step: &Step) {
+        if let Some(timestamp) = step.timestamp {
+            self.as_of = Some(self.as_of.map_or(timestamp, |as_of| as_of.max(timestamp)));
+        }
+    }
+
+    /// Writes a row per client, sorted by client, with amounts at `scale` decimal places.
+    pub fn write_csv<W: Write>(
+        &self,
+        records: impl IntoIterator<Item = ClientRecord>,
+        scale: u32,
+        writer: W,
+    ) -> Result<()> {
+        let to = self.options.currency;
+        let mut clients: BTreeMap<ClientId, Holdings> = BTreeMap::new();
+        for record in records {
+            let from = record.currency.with_context(|| {
+                format!("client {} has a balance without currency", record.client.0)
+            })?;
+            let rate = self
+                .options
+                .rates
+                .rate(from, to, self.as_of)
+                .with_context(|| format!("no rate from {from} to {to}"))?;
+            let convert = |amount: Decimal| {
+                amount
+                    .checked_mul(rate)
+                    .with_context(|| format!("converting {amount} {from} to {to} overflows"))
+            };
+            let holdings = clients.entry(record.client).or_default();
+            holdings.available = add(holdings.available, convert(record.available)?)?;
+            holdings.held = add(holdings.held, convert(record.held)?)?;
+            holdings.locked |= record.locked;
+        }
+
+        let mut writer = csv::Writer::from_writer(writer);
+        let round = |amount: Decimal| {
+            let mut amount = amount.round_dp_with_strategy(scale, self.options.rounding);
+            amount.rescale(scale);
+            amount
+        };
+        for (client, holdings) in clients {
+            let available = round(holdings.available);
+            let held = round(holdings.held);
+            writer.serialize(ConsolidatedRecord {
+                client,
+                currency: to,
+                available,
+                held,
+                total: add(available, held)?,
+                locked: holdings.locked,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn add(left: Decimal, right: Decimal) -> Result<Decimal> {
+    left.checked_add(right)
+        .with_context(|| format!("consolidated holdings overflow: {left} + {right}"))
+}
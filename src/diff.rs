@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use rust_decimal::Decimal;
+
+use crate::engine::ClientRecord;
+use crate::input::{csv_reader, records_with_lines};
+use crate::transaction::{ClientId, Currency};
+
+/// Differences between two accounts files, from the left one to the right one.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AccountsDiff {
+    /// Accounts only in the right file.
+    pub added: Vec<AccountKey>,
+    /// Accounts only in the left file.
+    pub removed: Vec<AccountKey>,
+    pub changed: Vec<FieldDifference>,
+}
+
+/// A row of an accounts file: a client, and the currency if the file has a currency column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AccountKey {
+    pub client: ClientId,
+    pub currency: Option<Currency>,
+}
+
+/// A field of an account that differs between the two files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldDifference {
+    Amount {
+        account: AccountKey,
+        field: &'static str,
+        left: Decimal,
+        right: Decimal,
+        /// `right - left`.
+        difference: Decimal,
+    },
+    Locked {
+        account: AccountKey,
+        left: bool,
+        right: bool,
+    },
+}
+
+impl fmt::Display for AccountKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "client {}", self.client.0)?;
+        if let Some(currency) = self.currency {
+            write!(f, " {currency}")?;
+        }
+        Ok(())
+    }
+}
+
+impl AccountsDiff {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.difference_count() == 0
+    }
+
+    #[must_use]
+    pub fn difference_count(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+}
+
+impl fmt::Display for AccountsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for account in &self.removed {
+            writeln!(f, "- {account}")?;
+        }
+        for account in &self.added {
+            writeln!(f, "+ {account}")?;
+        }
+        for difference in &self.changed {
+            match difference {
+                FieldDifference::Amount {
+                    account,
+                    field,
+                    left,
+                    right,
+                    difference,
+                } => writeln!(
+                    f,
+                    "~ {account}: {field} {left} -> {right} (diff {difference})"
+                )?,
+                FieldDifference::Locked {
+                    account,
+                    left,
+                    right,
+                } => writeln!(f, "~ {account}: locked {left} -> {right}")?,
+            }
+        }
+        match self.difference_count() {
+            0 => writeln!(f, "No differences."),
+            count => writeln!(f, "{count} differences."),
+        }
+    }
+}
+
+/// Reads an accounts file in the format the processor writes.
+pub fn read_accounts(filename: &str) -> Result<Vec<ClientRecord>> {
+    let mut reader =
+        csv_reader(filename).with_context(|| format!("failed to open accounts: {filename}"))?;
+    records_with_lines::<ClientRecord, _>(&mut reader)?
+        .map(|result| {
+            result
+                .map(|(_, record)| record)
+                .with_context(|| format!("failed to read accounts: {filename}"))
+        })
+        .collect()
+}
+
+/// Compares two sets of accounts, ignoring amount differences up to `tolerance`.
+pub fn compare(
+    left: &[ClientRecord],
+    right: &[ClientRecord],
+    tolerance: Decimal,
+) -> Result<AccountsDiff> {
+    let left = by_account(left, "left")?;
+    let right = by_account(right, "right")?;
+    let mut diff = AccountsDiff::default();
+
+    for (&account, left_record) in &left {
+        let Some(right_record) = right.get(&account) else {
+            diff.removed.push(account);
+            continue;
+        };
+        let amounts = [
+            ("available", left_record.available, right_record.available),
+            ("held", left_record.held, right_record.held),
+            ("total", left_record.total, right_record.total),
+        ];
+        for (field, left, right) in amounts {
+            let difference = right.checked_sub(left).with_context(|| {
+                format!("{account}: {field} difference overflows: {right} - {left}")
+            })?;
+            if difference.abs() > tolerance {
+                diff.changed.push(FieldDifference::Amount {
+                    account,
+                    field,
+                    left,
+                    right,
+                    difference,
+                });
+            }
+        }
+        if left_record.locked != right_record.locked {
+            diff.changed.push(FieldDifference::Locked {
+                account,
+                left: left_record.locked,
+                right: right_record.locked,
+            });
+        }
+    }
+    diff.added = right
+        .keys()
+        .filter(|account| !left.contains_key(account))
+        .copied()
+        .collect();
+    Ok(diff)
+}
+
+fn by_account<'a>(
+    records: &'a [ClientRecord],
+    side: &str,
+) -> Result<BTreeMap<AccountKey, &'a ClientRecord>> {
+    let mut by_account = BTreeMap::new();
+    for record in records {
+        let account = AccountKey {
+            client: record.client,
+            currency: record.currency,
+        };
+        if by_account.insert(account, record).is_some() {
+            bail!("duplicate {account} in {side} accounts");
+        }
+    }
+    Ok(by_account)
+}
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::engine::{Ledger, Step};
+use crate::transaction::{ClientId, TransactionId, TransactionType};
+
+/// Tracks the disputes that are still open, i.e. neither resolved nor charged back.
+#[derive(Debug, Default)]
+pub struct OpenDisputes {
+    open: HashMap<(ClientId, TransactionId), OpenDispute>,
+    rows: u64,
+    last_timestamp: Option<u64>,
+}
+
+/// A deposit under dispute and when the dispute was opened.
+#[derive(Debug, Clone, Copy)]
+pub struct OpenDispute {
+    pub amount: Amount,
+    /// Line of the dispute, or `None` if the deposit was already disputed in the open deposits.
+    pub line: Option<u64>,
+    pub timestamp: Option<u64>,
+    /// Number of rows processed before the dispute.
+    row: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenDisputeRecord {
+    client: ClientId,
+    tx: TransactionId,
+    amount: Amount,
+    line: Option<u64>,
+    open_rows: Option<u64>,
+    open_seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClientDisputesRecord {
+    client: ClientId,
+    disputes: usize,
+    #[serde(with = "rust_decimal::serde::str")]
+    amount: Decimal,
+    held: Amount,
+}
+
+impl OpenDisputes {
+    /// Starts from the deposits already under dispute in `ledger`.
+    #[must_use]
+    pub fn new(ledger: &Ledger) -> Self {
+        let open = ledger
+            .iter()
+            .flat_map(|(&client, state)| {
+                state
+                    .deposits
+                    .iter()
+                    .filter(|(_, deposit)| deposit.under_dispute)
+                    .map(move |(&tx, deposit)| {
+                        let dispute = OpenDispute {
+                            amount: deposit.amount,
+                            line: None,
+                            timestamp: None,
+                            row: 0,
+                        };
+                        ((client, tx), dispute)
+                    })
+            })
+            .collect();
+        OpenDisputes {
+            open,
+            ..OpenDisputes::default()
+        }
+    }
+
+    pub fn record(&mut self, step: &Step) {
+        self.rows += 1;
+        if step.timestamp.is_some() {
+            self.last_timestamp = step.timestamp;
+        }
+        if step.result.is_err() {
+            return;
+        }
+        let key = (step.client, step.tx);
+        match (step.tx_type, step.amount) {
+            (TransactionType::Dispute, Some(amount)) => {
+                let dispute = OpenDispute {
+                    amount,
+                    line: Some(step.line),
+                    timestamp: step.timestamp,
+                    row: self.rows,
+                };
+                self.open.insert(key, dispute);
+            }
+            (TransactionType::Resolve | TransactionType::Chargeback, _) => {
+                self.open.remove(&key);
+            }
+            _ => {}
+        }
+    }
+
+    /// Open disputes ordered by client, then by the line they were opened on.
+    #[must_use]
+    pub fn disputes(&self) -> Vec<(ClientId, TransactionId, OpenDispute)> {
+        let mut disputes: Vec<_> = self
+            .open
+            .iter()
+            .map(|(&(client, tx), &dispute)| (client, tx, dispute))
+            .collect();
+        disputes.sort_by_key(|(client, tx, dispute)| (client.0, dispute.line, tx.0));
+        disputes
+    }
+
+    /// Writes every open dispute with how long it has been open, in rows and, if the input
+    /// has timestamps, in seconds.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for (client, tx, dispute) in self.disputes() {
+            let opened_in_run = dispute.line.is_some();
+            writer.serialize(OpenDisputeRecord {
+                client,
+                tx,
+                amount: dispute.amount,
+                line: dispute.line,
+                open_rows: opened_in_run.then(|| self.rows - dispute.row),
+                open_seconds: self
+                    .last_timestamp
+                    .zip(dispute.timestamp)
+                    .map(|(now, opened)| now.saturating_sub(opened)),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the number and disputed amount of open disputes of every client that has any,
+    /// next to its held balance.
+    pub fn write_totals_csv<W: Write>(&self, ledger: &Ledger, writer: W) -> Result<()> {
+        // Summed in `i128`: under the debt shortfall policy, disputes aren't limited to what
+        // is held and their amounts can add up to more than an `Amount` can represent.
+        let mut totals: BTreeMap<u16, (usize, i128)> = BTreeMap::new();
+        for (&(client, _), dispute) in &self.open {
+            let (count, amount) = totals.entry(client.0).or_default();
+            *count += 1;
+            *amount += i128::from(dispute.amount.units());
+        }
+
+        let mut writer = csv::Writer::from_writer(writer);
+        for (client, (disputes, amount)) in totals {
+            let client = ClientId(client);
+            writer.serialize(ClientDisputesRecord {
+                client,
+                disputes,
+                amount: Decimal::from_i128_with_scale(amount, Amount::SCALE),
+                held: ledger.get(&client).map_or(Amount::ZERO, |state| state.held),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,932 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::access::ClientAccess;
+use crate::amount::Amount;
+use crate::fees::{Fee, FeeSchedule};
+use crate::limits::{Limits, OverdraftLimit, OverdraftLimits};
+use crate::policy::{DisputeShortfall, Policy};
+use crate::precision::Precision;
+use crate::rejection::Rejection;
+use crate::transaction::{
+    ClientId, Currency, Transaction, TransactionId, TransactionRecord, TransactionType,
+};
+use crate::velocity::{VelocityLimits, VelocityWindow};
+
+pub type Ledger = HashMap<ClientId, ClientState>;
+
+/// Applies input rows to a ledger one at a time.
+#[derive(Debug)]
+pub struct Processor {
+    ledger: Ledger,
+    precision: Precision,
+    limits: Limits,
+    policy: Policy,
+    fees: Option<FeeSchedule>,
+    overdrafts: Option<OverdraftLimits>,
+    velocity: Option<VelocityLimits>,
+    access: ClientAccess,
+    /// A ledger per currency if the input has a currency column, in which case `ledger` holds
+    /// no balances of its own.
+    currency_ledgers: Option<BTreeMap<Currency, Ledger>>,
+}
+
+/// Outcome of processing a single input row.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub line: u64,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub tx_type: TransactionType,
+    pub timestamp: Option<u64>,
+    /// The currency of the balances the row applies to, if the input has a currency column.
+    pub currency: Option<Currency>,
+    /// The amount of a deposit or withdrawal, or of the deposit an applied dispute, resolve or
+    /// chargeback refers to. A dispute under the debt shortfall policy still has the whole
+    /// amount of the deposit, of which only part may have been held.
+    pub amount: Option<Amount>,
+    /// The fee charged on an applied withdrawal, if there is a fee schedule.
+    pub fee: Option<Fee>,
+    /// The client credited by a transfer. The balances are those of the debited client.
+    pub destination: Option<ClientId>,
+    /// The destination of an applied transfer, or the house account credited with the fee of
+    /// an applied withdrawal unless it's the client itself.
+    pub counterparty: Option<Counterparty>,
+    pub result: Result<(), Rejection>,
+    /// The client's balances before the row, or `None` if the client had no account.
+    pub before: Option<ClientSnapshot>,
+    /// The client's balances after the row, or `None` if the client has no account.
+    pub after: Option<ClientSnapshot>,
+}
+
+/// Another client whose balances an applied row changed.
+#[derive(Debug, Clone, Copy)]
+pub struct Counterparty {
+    pub client: ClientId,
+    /// Why the client was credited: `fee` or `transfer`.
+    pub operation: &'static str,
+    /// The amount credited to the client.
+    pub amount: Amount,
+    /// The client's balances before the row, or `None` if it had no account.
+    pub before: Option<ClientSnapshot>,
+    pub after: Option<ClientSnapshot>,
+}
+
+/// A client's balances at a point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientSnapshot {
+    pub available: Amount,
+    pub held: Amount,
+    pub locked: bool,
+}
+
+impl ClientSnapshot {
+    /// Sum of available and held. Can't overflow as the engine keeps it representable.
+    #[must_use]
+    pub fn total(&self) -> Amount {
+        Amount::from_units(self.available.units() + self.held.units())
+    }
+}
+
+impl Processor {
+    #[must_use]
+    pub fn new(ledger: Ledger, precision: Precision, limits: Limits) -> Self {
+        Processor {
+            ledger,
+            precision,
+            limits,
+            policy: Policy::default(),
+            fees: None,
+            overdrafts: None,
+            velocity: None,
+            access: ClientAccess::default(),
+            currency_ledgers: None,
+        }
+    }
+
+    /// Applies `policy` instead of the default rules.
+    #[must_use]
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Charges withdrawal fees according to `fees`.
+    #[must_use]
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = Some(fees);
+        self
+    }
+
+    /// Lets the clients in `overdrafts` withdraw beyond their available balance.
+    #[must_use]
+    pub fn with_overdrafts(mut self, overdrafts: OverdraftLimits) -> Self {
+        self.overdrafts = Some(overdrafts);
+        self
+    }
+
+    /// Rejects deposits and withdrawals beyond the rolling-window `velocity` limits.
+    #[must_use]
+    pub fn with_velocity_limits(mut self, velocity: VelocityLimits) -> Self {
+        self.velocity = Some(velocity);
+        self
+    }
+
+    /// Rejects every transaction of the clients `access` doesn't allow.
+    #[must_use]
+    pub fn with_access(mut self, access: ClientAccess) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Keeps separate balances per currency, for inputs with a currency column. Rows then
+    /// need a currency, except disputes, resolves and chargebacks which use the deposit's.
+    #[must_use]
+    pub fn with_currencies(mut self) -> Self {
+        self.currency_ledgers = Some(BTreeMap::new());
+        self
+    }
+
+    /// The balances of rows without a currency, which are all of them unless the processor
+    /// keeps separate balances per currency.
+    #[must_use]
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// The balances per currency, if the processor keeps them separately.
+    #[must_use]
+    pub fn currency_ledgers(&self) -> Option<&BTreeMap<Currency, Ledger>> {
+        self.currency_ledgers.as_ref()
+    }
+
+    /// Number of distinct clients with an account, in any currency.
+    #[must_use]
+    pub fn client_count(&self) -> usize {
+        match &self.currency_ledgers {
+            Some(ledgers) => {
+                let clients: std::collections::HashSet<ClientId> = ledgers
+                    .values()
+                    .flat_map(|ledger| ledger.keys().copied())
+                    .collect();
+                clients.len()
+            }
+            None => self.ledger.len(),
+        }
+    }
+
+    #[must_use]
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Validates and applies a single input row, leaving the ledger untouched if it's rejected
+    /// (apart from opening the account if the policy lets any transaction do so).
+    pub fn process(&mut self, line: u64, record: &TransactionRecord) -> Step {
+        let currency = self.currency_of(record);
+        let snapshot = |processor: &Self, client| {
+            processor
+                .ledger_for(currency)
+                .and_then(|ledger| ledger.get(&client))
+                .map(ClientState::snapshot)
+        };
+        let before = snapshot(self, record.client);
+        // A transfer credits its destination, and a withdrawal the house account with its fee.
+        let destination = record
+            .destination
+            .filter(|_| record.tx_type == TransactionType::Transfer);
+        let house = self
+            .fees
+            .as_ref()
+            .map(|fees| fees.house_account)
+            .filter(|&house| {
+                record.tx_type == TransactionType::Withdrawal && house != record.client
+            });
+        let credited = destination.or(house);
+        let credited_before = credited.and_then(|credited| snapshot(self, credited));
+        let (amount, fee, result) = match Transaction::from_record(record, self.precision) {
+            Ok(transaction) => match self.apply(line, record.timestamp, currency, transaction) {
+                Ok((amount, fee)) => (Some(amount), fee, Ok(())),
+                Err(rejection) => (transaction.amount(), None, Err(rejection)),
+            },
+            Err(e) => (None, None, Err(Rejection::InvalidRecord(e.to_string()))),
+        };
+        let credit = match (destination, fee) {
+            (Some(destination), _) => amount
+                .filter(|_| result.is_ok())
+                .map(|amount| ("transfer", destination, amount)),
+            (None, Some(fee)) if house == Some(fee.house) => Some(("fee", fee.house, fee.amount)),
+            (None, _) => None,
+        };
+        let counterparty = credit.map(|(operation, client, amount)| Counterparty {
+            client,
+            operation,
+            amount,
+            before: credited_before,
+            after: snapshot(self, client),
+        });
+        Step {
+            line,
+            client: record.client,
+            tx: record.tx,
+            tx_type: record.tx_type,
+            timestamp: record.timestamp,
+            currency,
+            amount,
+            fee,
+            destination,
+            counterparty,
+            result,
+            before,
+            after: snapshot(self, record.client),
+        }
+    }
+
+    /// The currency a row applies to: its own, or the currency of the deposit it refers to if
+    /// it doesn't have one. Always `None` without separate balances per currency.
+    fn currency_of(&self, record: &TransactionRecord) -> Option<Currency> {
+        let ledgers = self.currency_ledgers.as_ref()?;
+        record.currency.or_else(|| {
+            ledgers.iter().find_map(|(&currency, ledger)| {
+                ledger
+                    .get(&record.client)
+                    .filter(|state| state.deposits.contains_key(&record.tx))
+                    .map(|_| currency)
+            })
+        })
+    }
+
+    fn ledger_for(&self, currency: Option<Currency>) -> Option<&Ledger> {
+        match (&self.currency_ledgers, currency) {
+            (None, _) => Some(&self.ledger),
+            (Some(ledgers), Some(currency)) => ledgers.get(&currency),
+            (Some(_), None) => None,
+        }
+    }
+
+    /// Whether the client has a deposit `tx` in a currency other than `currency`.
+    fn deposited_elsewhere(
+        &self,
+        client: ClientId,
+        tx: TransactionId,
+        currency: Option<Currency>,
+    ) -> bool {
+        self.currency_ledgers
+            .iter()
+            .flatten()
+            .any(|(&other, ledger)| {
+                Some(other) != currency
+                    && ledger
+                        .get(&client)
+                        .is_some_and(|state| state.deposits.contains_key(&tx))
+            })
+    }
+
+    /// Whether the client's account is locked in a currency other than `currency`.
+    fn locked_elsewhere(&self, client: ClientId, currency: Option<Currency>) -> bool {
+        self.currency_ledgers
+            .iter()
+            .flatten()
+            .any(|(&other, ledger)| {
+                Some(other) != currency && ledger.get(&client).is_some_and(|state| state.locked)
+            })
+    }
+
+    /// Applies a transaction with the settings of the processor, returning the amount and
+    /// any fee.
+    fn apply(
+        &mut self,
+        line: u64,
+        timestamp: Option<u64>,
+        currency: Option<Currency>,
+        transaction: Transaction,
+    ) -> Result<(Amount, Option<Fee>), Rejection> {
+        let client = transaction.client();
+        let operation = transaction.tx_type().label();
+        self.access.check(client)?;
+        let destination = match transaction {
+            Transaction::Transfer { destination, .. } => {
+                self.access.check(destination)?;
+                Some(destination)
+            }
+            _ => None,
+        };
+        let fee = self.withdrawal_fee(transaction)?;
+        let overdraft = self
+            .overdrafts
+            .as_ref()
+            .and_then(|overdrafts| overdrafts.get(&client))
+            .copied()
+            .unwrap_or_default();
+        // Transaction ids are unique per client across currencies, so that a dispute without
+        // a currency refers to a single deposit.
+        if let Transaction::Deposit { tx, .. } = transaction
+            && self.deposited_elsewhere(client, tx, currency)
+        {
+            return Err(Rejection::DuplicateTransaction(tx));
+        }
+        let locked_elsewhere = self.locked_elsewhere(client, currency);
+        // The other client the transaction credits, if any.
+        let credited = destination.or(fee.map(|fee| fee.house));
+        let credited_locked_elsewhere = credited
+            .filter(|&credited| credited != client && self.locked_elsewhere(credited, currency));
+
+        let ledger = match (&mut self.currency_ledgers, currency) {
+            (None, _) => &mut self.ledger,
+            (Some(ledgers), Some(currency)) => ledgers.entry(currency).or_default(),
+            (Some(_), None) => {
+                // Disputes, resolves and chargebacks only lack a currency if there's no deposit
+                // to take it from.
+                return Err(match transaction {
+                    Transaction::Dispute { tx, .. }
+                    | Transaction::Resolve { tx, .. }
+                    | Transaction::Chargeback { tx, .. } => {
+                        Rejection::UnknownTransaction { operation, tx }
+                    }
+                    Transaction::Deposit { .. }
+                    | Transaction::Withdrawal { .. }
+                    | Transaction::Transfer { .. } => {
+                        Rejection::InvalidRecord("missing currency".to_string())
+                    }
+                });
+            }
+        };
+        // A client locked in one currency can't open an account in another.
+        if locked_elsewhere && !ledger.contains_key(&client) {
+            return Err(Rejection::LockedAccount { operation, client });
+        }
+        if let Some(credited) = credited_locked_elsewhere
+            && !ledger.contains_key(&credited)
+        {
+            return Err(Rejection::LockedAccount {
+                operation,
+                client: credited,
+            });
+        }
+        if let Some(velocity) = &self.velocity {
+            let mut no_windows = Vec::new();
+            let windows = match ledger.get_mut(&client) {
+                Some(client_state) => &mut client_state.velocity,
+                None => &mut no_windows,
+            };
+            velocity.check(windows, line, timestamp, transaction)?;
+        }
+        let amount = process_transaction(
+            ledger,
+            &self.limits,
+            self.policy,
+            Withdrawal { fee, overdraft },
+            transaction,
+        )?;
+        if let Some(velocity) = &self.velocity
+            && let Some(client_state) = ledger.get_mut(&client)
+        {
+            velocity.record(&mut client_state.velocity, line, timestamp, transaction);
+        }
+        // A chargeback locks the client's accounts in every currency.
+        if let (Transaction::Chargeback { .. }, Some(ledgers)) =
+            (transaction, &mut self.currency_ledgers)
+        {
+            for client_state in ledgers
+                .values_mut()
+                .filter_map(|ledger| ledger.get_mut(&client))
+            {
+                client_state.locked = true;
+            }
+        }
+        Ok((amount, fee))
+    }
+
+    /// The fee of a withdrawal under the fee schedule, if there is one and it isn't free.
+    fn withdrawal_fee(&self, transaction: Transaction) -> Result<Option<Fee>, Rejection> {
+        let (Some(fees), Transaction::Withdrawal { client, amount }) = (&self.fees, transaction)
+        else {
+            return Ok(None);
+        };
+        let amount = fees
+            .fee(amount, self.precision.max_scale())
+            .ok_or(Rejection::Overflow {
+                operation: "withdrawal",
+                client,
+            })?;
+        Ok((amount > Amount::ZERO).then_some(Fee {
+            house: fees.house_account,
+            amount,
+        }))
+    }
+
+    /// Output records of all clients, in no particular order.
+    pub fn client_records(&self) -> impl Iterator<Item = ClientRecord> + '_ {
+        let columns = OptionalColumns {
+            debt: self.policy.dispute_shortfall == DisputeShortfall::Debt,
+            fees: self.fees.is_some(),
+            overdraft: self.overdrafts.is_some(),
+        };
+        let currency_ledgers = self
+            .currency_ledgers
+            .iter()
+            .flatten()
+            .map(|(&currency, ledger)| (Some(currency), ledger));
+        std::iter::once((None, &self.ledger))
+            .chain(currency_ledgers)
+            .flat_map(move |(currency, ledger)| {
+                ledger.iter().map(move |(&client, state)| ClientRecord {
+                    currency,
+                    ..state.to_client_record(client, self.precision, columns)
+                })
+            })
+    }
+}
+
+/// Client-specific terms of a withdrawal.
+#[derive(Debug, Clone, Copy)]
+struct Withdrawal {
+    fee: Option<Fee>,
+    overdraft: OverdraftLimit,
+}
+
+fn process_transaction(
+    ledger: &mut Ledger,
+    limits: &Limits,
+    policy: Policy,
+    withdrawal: Withdrawal,
+    transaction: Transaction,
+) -> Result<Amount, Rejection> {
+    if !policy.only_deposits_create_accounts {
+        ledger.entry(transaction.client()).or_default();
+    }
+    match transaction {
+        Transaction::Deposit { client, tx, amount } => {
+            process_deposit(ledger, limits, client, tx, amount)
+        }
+        Transaction::Withdrawal { client, amount } => {
+            process_withdrawal(ledger, limits, policy, client, amount, withdrawal)
+        }
+        Transaction::Dispute { client, tx } => process_dispute(ledger, policy, client, tx),
+        Transaction::Resolve { client, tx } => process_resolve(ledger, policy, client, tx),
+        Transaction::Chargeback { client, tx } => process_chargeback(ledger, policy, client, tx),
+        Transaction::Transfer {
+            client,
+            destination,
+            amount,
+        } => process_transfer(ledger, limits, policy, client, destination, amount),
+    }
+}
+
+fn process_deposit(
+    ledger: &mut Ledger,
+    limits: &Limits,
+    client: ClientId,
+    tx: TransactionId,
+    amount: Amount,
+) -> Result<Amount, Rejection> {
+    limits.check_amount(amount)?;
+    let current_total = match ledger.get(&client) {
+        Some(client_state) => {
+            client_state.check_unlocked("deposit", client)?;
+            if client_state.deposits.contains_key(&tx) {
+                return Err(Rejection::DuplicateTransaction(tx));
+            }
+            client_state.total()
+        }
+        None => Amount::ZERO,
+    };
+    let new_total = current_total
+        .checked_add(amount)
+        .ok_or(Rejection::Overflow {
+            operation: "deposit",
+            client,
+        })?;
+    limits.check_balance(client, new_total)?;
+
+    let client_state = ledger.entry(client).or_default();
+    let repaid = amount.min(client_state.debt);
+    client_state.apply_balances(
+        "deposit",
+        client,
+        Amount::from_units(amount.units() - repaid.units()),
+        Amount::ZERO,
+    )?;
+    client_state.debt = Amount::from_units(client_state.debt.units() - repaid.units());
+    client_state.deposits.insert(
+        tx,
+        StoredDeposit {
+            amount,
+            under_dispute: false,
+            held: Amount::ZERO,
+            resolved: false,
+        },
+    );
+    Ok(amount)
+}
+
+fn process_withdrawal(
+    ledger: &mut Ledger,
+    limits: &Limits,
+    policy: Policy,
+    client: ClientId,
+    amount: Amount,
+    withdrawal: Withdrawal,
+) -> Result<Amount, Rejection> {
+    limits.check_amount(amount)?;
+    let fee = withdrawal.fee;
+    let overflow = Rejection::Overflow {
+        operation: "withdrawal",
+        client,
+    };
+    let fee_amount = fee.map_or(Amount::ZERO, |fee| fee.amount);
+    let debit = amount.checked_add(fee_amount).ok_or(overflow.clone())?;
+    let client_state = get_client_mut(ledger, "withdrawal", client)?;
+    client_state.check_unlocked("withdrawal", client)?;
+    withdrawal
+        .overdraft
+        .check_withdrawal(client, client_state.available, amount, debit)?;
+    let fees = client_state.fees.checked_add(fee_amount).ok_or(overflow)?;
+    // Check the house account can take the fee before changing any balances.
+    if let Some(fee) = fee
+        && fee.house != client
+    {
+        let overflow = Rejection::Overflow {
+            operation: "withdrawal fee",
+            client: fee.house,
+        };
+        let house_total = match ledger.get(&fee.house) {
+            Some(house) => {
+                house.check_unlocked("withdrawal fee", fee.house)?;
+                house
+                    .available
+                    .checked_add(fee.amount)
+                    .ok_or(overflow.clone())?;
+                house.total()
+            }
+            None if policy.only_deposits_create_accounts => {
+                return Err(Rejection::UnknownAccount {
+                    operation: "withdrawal fee",
+                    client: fee.house,
+                });
+            }
+            None => Amount::ZERO,
+        };
+        let new_total = house_total.checked_add(fee.amount).ok_or(overflow)?;
+        limits.check_balance(fee.house, new_total)?;
+    }
+
+    let client_state = get_client_mut(ledger, "withdrawal", client)?;
+    client_state.apply_balances("withdrawal", client, -debit, Amount::ZERO)?;
+    client_state.fees = fees;
+    if let Some(fee) = fee {
+        ledger.entry(fee.house).or_default().apply_balances(
+            "withdrawal fee",
+            fee.house,
+            fee.amount,
+            Amount::ZERO,
+        )?;
+    }
+    Ok(amount)
+}
+
+fn process_dispute(
+    ledger: &mut Ledger,
+    policy: Policy,
+    client: ClientId,
+    tx: TransactionId,
+) -> Result<Amount, Rejection> {
+    let client_state = get_client_mut(ledger, "dispute", client)?;
+    if policy.locked_rejects_disputes {
+        client_state.check_unlocked("dispute", client)?;
+    }
+    let deposit = client_state.get_deposit(tx, "dispute")?;
+    if deposit.under_dispute {
+        return Err(Rejection::AlreadyDisputed(tx));
+    }
+    if deposit.resolved && !policy.redispute_resolved {
+        return Err(Rejection::AlreadyResolved(tx));
+    }
+
+    let amount = deposit.amount;
+    let available = client_state.available;
+    let held = match policy.dispute_shortfall {
+        DisputeShortfall::Reject if available < amount => {
+            return Err(Rejection::InsufficientFunds {
+                client,
+                available,
+                requested: amount,
+            });
+        }
+        DisputeShortfall::Debt => amount.min(available.max(Amount::ZERO)),
+        DisputeShortfall::Overdraw | DisputeShortfall::Reject => amount,
+    };
+    let shortfall = Amount::from_units(amount.units() - held.units());
+    let debt = client_state
+        .debt
+        .checked_add(shortfall)
+        .ok_or(Rejection::Overflow {
+            operation: "dispute",
+            client,
+        })?;
+    client_state.apply_balances("dispute", client, -held, held)?;
+    client_state.debt = debt;
+    let deposit = client_state.get_deposit_mut(tx, "dispute")?;
+    deposit.under_dispute = true;
+    deposit.held = held;
+    Ok(amount)
+}
+
+fn process_resolve(
+    ledger: &mut Ledger,
+    policy: Policy,
+    client: ClientId,
+    tx: TransactionId,
+) -> Result<Amount, Rejection> {
+    let client_state = get_client_mut(ledger, "resolve", client)?;
+    if policy.locked_rejects_disputes {
+        client_state.check_unlocked("resolve", client)?;
+    }
+    let deposit = client_state.get_deposit(tx, "resolve")?;
+    if !deposit.under_dispute {
+        return Err(Rejection::NotDisputed {
+            operation: "resolve",
+            tx,
+        });
+    }
+
+    // Cancel the debt the dispute caused, returning what was already paid off of it.
+    let amount = deposit.amount;
+    let held = deposit.held;
+    let shortfall = Amount::from_units(amount.units() - held.units());
+    let cancelled = shortfall.min(client_state.debt);
+    let returned = Amount::from_units(shortfall.units() - cancelled.units());
+    let released = held.checked_add(returned).ok_or(Rejection::Overflow {
+        operation: "resolve",
+        client,
+    })?;
+    client_state.apply_balances("resolve", client, released, -held)?;
+    client_state.debt = Amount::from_units(client_state.debt.units() - cancelled.units());
+    let deposit = client_state.get_deposit_mut(tx, "resolve")?;
+    deposit.under_dispute = false;
+    deposit.resolved = true;
+    Ok(amount)
+}
+
+fn process_chargeback(
+    ledger: &mut Ledger,
+    policy: Policy,
+    client: ClientId,
+    tx: TransactionId,
+) -> Result<Amount, Rejection> {
+    let client_state = get_client_mut(ledger, "chargeback", client)?;
+    if policy.locked_rejects_disputes {
+        client_state.check_unlocked("chargeback", client)?;
+    }
+    let deposit = client_state.get_deposit(tx, "chargeback")?;
+    if !deposit.under_dispute {
+        return Err(Rejection::NotDisputed {
+            operation: "chargeback",
+            tx,
+        });
+    }
+
+    // Any debt from the dispute stays with the client.
+    let amount = deposit.amount;
+    let held = deposit.held;
+    client_state.apply_balances("chargeback", client, Amount::ZERO, -held)?;
+    client_state
+        .get_deposit_mut(tx, "chargeback")?
+        .under_dispute = false;
+    client_state.locked = true;
+    Ok(amount)
+}
+
+/// Moves funds between the available balances of two clients, opening the destination's
+/// account if needed. Both sides are checked before any balance changes.
+fn process_transfer(
+    ledger: &mut Ledger,
+    limits: &Limits,
+    policy: Policy,
+    client: ClientId,
+    destination: ClientId,
+    amount: Amount,
+) -> Result<Amount, Rejection> {
+    limits.check_amount(amount)?;
+    let source = get_client_mut(ledger, "transfer", client)?;
+    source.check_unlocked("transfer", client)?;
+    if source.available < amount {
+        return Err(Rejection::InsufficientFunds {
+            client,
+            available: source.available,
+            requested: amount,
+        });
+    }
+    let overflow = Rejection::Overflow {
+        operation: "transfer",
+        client: destination,
+    };
+    let current_total = match ledger.get(&destination) {
+        Some(destination_state) => {
+            destination_state.check_unlocked("transfer", destination)?;
+            destination_state
+                .available
+                .checked_add(amount)
+                .ok_or(overflow.clone())?;
+            destination_state.total()
+        }
+        None if policy.only_deposits_create_accounts => {
+            return Err(Rejection::UnknownAccount {
+                operation: "transfer",
+                client: destination,
+            });
+        }
+        None => Amount::ZERO,
+    };
+    let new_total = current_total.checked_add(amount).ok_or(overflow)?;
+    limits.check_balance(destination, new_total)?;
+
+    get_client_mut(ledger, "transfer", client)?.apply_balances(
+        "transfer",
+        client,
+        -amount,
+        Amount::ZERO,
+    )?;
+    // The destination is credited like a deposit, paying off its debt first.
+    let destination_state = ledger.entry(destination).or_default();
+    let repaid = amount.min(destination_state.debt);
+    destination_state.apply_balances(
+        "transfer",
+        destination,
+        Amount::from_units(amount.units() - repaid.units()),
+        Amount::ZERO,
+    )?;
+    destination_state.debt = Amount::from_units(destination_state.debt.units() - repaid.units());
+    Ok(amount)
+}
+
+fn get_client_mut<'a>(
+    ledger: &'a mut Ledger,
+    operation: &'static str,
+    client: ClientId,
+) -> Result<&'a mut ClientState, Rejection> {
+    ledger
+        .get_mut(&client)
+        .ok_or(Rejection::UnknownAccount { operation, client })
+}
+
+/// A client's final balances as written to the output.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientRecord {
+    pub client: ClientId,
+    /// Only written for inputs with a currency column.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    // Read as strings: the CSV reader would otherwise go through a float and lose the scale.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub available: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub held: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub total: Decimal,
+    pub locked: bool,
+    /// Only written when disputes can leave the client with a debt.
+    #[serde(
+        default,
+        with = "rust_decimal::serde::str_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub debt: Option<Decimal>,
+    /// Total withdrawal fees paid, only written when there is a fee schedule.
+    #[serde(
+        default,
+        with = "rust_decimal::serde::str_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fees: Option<Decimal>,
+    /// How far available is below zero, only written when there are overdraft limits.
+    #[serde(
+        default,
+        with = "rust_decimal::serde::str_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub overdraft_used: Option<Decimal>,
+}
+
+/// Output columns that are only written when the settings of a run make them meaningful.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OptionalColumns {
+    pub debt: bool,
+    pub fees: bool,
+    pub overdraft: bool,
+}
+
+#[derive(Debug)]
+pub struct StoredDeposit {
+    pub(crate) amount: Amount,
+    pub(crate) under_dispute: bool,
+    /// Part of the amount held while under dispute, less than the amount if the client
+    /// couldn't cover all of it.
+    pub(crate) held: Amount,
+    /// Whether a dispute of the deposit was resolved.
+    pub(crate) resolved: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ClientState {
+    pub(crate) deposits: HashMap<TransactionId, StoredDeposit>,
+    pub(crate) available: Amount,
+    pub(crate) held: Amount,
+    pub(crate) locked: bool,
+    /// Amount the client owes for disputes it couldn't cover, paid off by later deposits.
+    pub(crate) debt: Amount,
+    /// Total fees the client paid on withdrawals.
+    pub(crate) fees: Amount,
+    /// Recent deposits and withdrawals, one window per velocity limit.
+    pub(crate) velocity: Vec<VelocityWindow>,
+}
+
+impl ClientState {
+    fn check_unlocked(&self, operation: &'static str, client: ClientId) -> Result<(), Rejection> {
+        if self.locked {
+            return Err(Rejection::LockedAccount { operation, client });
+        }
+        Ok(())
+    }
+
+    fn get_deposit(
+        &self,
+        tx: TransactionId,
+        operation: &'static str,
+    ) -> Result<&StoredDeposit, Rejection> {
+        self.deposits
+            .get(&tx)
+            .ok_or(Rejection::UnknownTransaction { operation, tx })
+    }
+
+    fn get_deposit_mut(
+        &mut self,
+        tx: TransactionId,
+        operation: &'static str,
+    ) -> Result<&mut StoredDeposit, Rejection> {
+        self.deposits
+            .get_mut(&tx)
+            .ok_or(Rejection::UnknownTransaction { operation, tx })
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> ClientSnapshot {
+        ClientSnapshot {
+            available: self.available,
+            held: self.held,
+            locked: self.locked,
+        }
+    }
+
+    /// Sum of available and held. Can't overflow as `apply_balances` keeps it representable.
+    #[must_use]
+    pub fn total(&self) -> Amount {
+        Amount::from_units(self.available.units() + self.held.units())
+    }
+
+    /// Adjusts available and held by the given deltas, leaving the balances untouched if
+    /// any of available, held or total would overflow.
+    fn apply_balances(
+        &mut self,
+        operation: &'static str,
+        client: ClientId,
+        available_delta: Amount,
+        held_delta: Amount,
+    ) -> Result<(), Rejection> {
+        let overflow = Rejection::Overflow { operation, client };
+        let available = self
+            .available
+            .checked_add(available_delta)
+            .ok_or(overflow.clone())?;
+        let held = self.held.checked_add(held_delta).ok_or(overflow.clone())?;
+        available.checked_add(held).ok_or(overflow)?;
+
+        self.available = available;
+        self.held = held;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn to_client_record(
+        &self,
+        client: ClientId,
+        precision: Precision,
+        columns: OptionalColumns,
+    ) -> ClientRecord {
+        ClientRecord {
+            client,
+            currency: None,
+            available: precision.normalize(self.available),
+            held: precision.normalize(self.held),
+            total: precision.normalize(self.total()),
+            locked: self.locked,
+            debt: columns.debt.then(|| precision.normalize(self.debt)),
+            fees: columns.fees.then(|| precision.normalize(self.fees)),
+            overdraft_used: columns
+                .overdraft
+                .then(|| precision.normalize((-self.available).max(Amount::ZERO))),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::io::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::engine::{ClientSnapshot, Step};
+use crate::transaction::{ClientId, Currency, TransactionId};
+
+/// Writes a newline-delimited JSON event for every applied transaction.
+#[derive(Debug)]
+pub struct EventWriter<W: Write> {
+    writer: W,
+}
+
+#[derive(Debug, Serialize)]
+struct BalanceEvent {
+    line: u64,
+    client: ClientId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    tx: TransactionId,
+    operation: &'static str,
+    before: EventBalances,
+    after: EventBalances,
+}
+
+#[derive(Debug, Serialize)]
+struct EventBalances {
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    locked: bool,
+}
+
+impl From<Option<ClientSnapshot>> for EventBalances {
+    /// A client without an account is reported with zero balances.
+    fn from(snapshot: Option<ClientSnapshot>) -> Self {
+        let snapshot = snapshot.unwrap_or_default();
+        EventBalances {
+            available: snapshot.available,
+            held: snapshot.held,
+            total: snapshot.total(),
+            locked: snapshot.locked,
+        }
+    }
+}
+
+impl<W: Write> EventWriter<W> {
+    pub fn new(writer: W) -> Self {
+        EventWriter { writer }
+    }
+
+    /// Writes the event of an applied step, followed by a `transfer` event for the destination
+    /// of a transfer or a `fee` event for the house account if it was credited. Rejected steps
+    /// don't change any balance and are skipped.
+    pub fn write(&mut self, step: &Step) -> Result<()> {
+        if step.result.is_err() {
+            return Ok(());
+        }
+        self.write_event(&BalanceEvent {
+            line: step.line,
+            client: step.client,
+            currency: step.currency,
+            tx: step.tx,
+            operation: step.tx_type.label(),
+            before: step.before.into(),
+            after: step.after.into(),
+        })?;
+        if let Some(counterparty) = step.counterparty {
+            self.write_event(&BalanceEvent {
+                line: step.line,
+                client: counterparty.client,
+                currency: step.currency,
+                tx: step.tx,
+                operation: counterparty.operation,
+                before: counterparty.before.into(),
+                after: counterparty.after.into(),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &BalanceEvent) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+
+use crate::engine::ClientSnapshot;
+use crate::history::HistoryEntry;
+use crate::transaction::{ClientId, TransactionType};
+
+/// Human-readable account of how a client's balances came about, built from its history.
+#[derive(Debug)]
+pub struct Explanation<'a> {
+    client: ClientId,
+    /// The client's balance before its first transaction, if it had an opening balance.
+    opening: Option<ClientSnapshot>,
+    entries: &'a [HistoryEntry],
+}
+
+impl<'a> Explanation<'a> {
+    #[must_use]
+    pub fn new(
+        client: ClientId,
+        opening: Option<ClientSnapshot>,
+        entries: &'a [HistoryEntry],
+    ) -> Self {
+        Explanation {
+            client,
+            opening,
+            entries,
+        }
+    }
+
+    fn write_entry(f: &mut fmt::Formatter<'_>, entry: &HistoryEntry) -> fmt::Result {
+        let operation = entry.operation();
+        let tx = entry.tx.0;
+        write!(f, "line {}: ", entry.line)?;
+        match (entry.credited_by, entry.tx_type, entry.amount) {
+            (Some(from), _, Some(amount)) => {
+                write!(f, "{operation} {amount} from client {} (tx {tx})", from.0)?;
+            }
+            (_, TransactionType::Deposit | TransactionType::Withdrawal, Some(amount)) => {
+                write!(f, "{operation} {amount} (tx {tx})")?;
+            }
+            (_, _, Some(amount)) => write!(f, "{operation} of tx {tx} ({amount})")?,
+            (_, _, None) => write!(f, "{operation} of tx {tx}")?,
+        }
+        match &entry.rejection {
+            Some(rejection) => writeln!(f, " was rejected: {rejection}")?,
+            None => writeln!(f, " was applied")?,
+        }
+        match entry.after {
+            Some(after) => writeln!(f, "  balance: {}", Balance(after)),
+            None => writeln!(f, "  balance: no account"),
+        }
+    }
+
+    fn write_summary(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.entries.iter().rev().find_map(|entry| entry.after);
+        let Some(last) = last.or(self.opening) else {
+            return writeln!(f, "Client {} has no account.", self.client.0);
+        };
+        writeln!(f, "Final balance: {}", Balance(last))?;
+
+        let applied = |tx_type| {
+            self.entries
+                .iter()
+                .filter(move |entry| entry.rejection.is_none() && entry.tx_type == tx_type)
+        };
+        if last.locked {
+            let chargeback = applied(TransactionType::Chargeback).next_back();
+            match chargeback {
+                Some(entry) => writeln!(
+                    f,
+                    "The account is locked since the chargeback of tx {} on line {}.",
+                    entry.tx.0, entry.line
+                )?,
+                None => writeln!(f, "The account was already locked in the opening balances.")?,
+            }
+        }
+        if last.available.is_negative() {
+            let disputes: Vec<String> = applied(TransactionType::Dispute)
+                .filter(|entry| {
+                    entry
+                        .after
+                        .is_some_and(|after| after.available.is_negative())
+                })
+                .map(|entry| format!("line {} (tx {})", entry.line, entry.tx.0))
+                .collect();
+            if !disputes.is_empty() {
+                writeln!(
+                    f,
+                    "Available is negative because funds were disputed after being withdrawn: {}.",
+                    disputes.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Explanation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.entries.is_empty() {
+            writeln!(
+                f,
+                "No transactions for client {} in the input.",
+                self.client.0
+            )?;
+            if self.opening.is_none() {
+                return Ok(());
+            }
+        } else {
+            writeln!(f, "Transactions of client {}:", self.client.0)?;
+        }
+        if let Some(opening) = self.opening {
+            writeln!(f, "Opening balance: {}", Balance(opening))?;
+        }
+        for entry in self.entries {
+            Self::write_entry(f, entry)?;
+        }
+        self.write_summary(f)
+    }
+}
+
+struct Balance(ClientSnapshot);
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Balance(snapshot) = self;
+        write!(
+            f,
+            "available {}, held {}, total {}{}",
+            snapshot.available,
+            snapshot.held,
+            snapshot.total(),
+            if snapshot.locked { ", locked" } else { "" }
+        )
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{Context, Result, bail};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+
+use crate::amount::Amount;
+use crate::transaction::ClientId;
+
+/// Fees charged on withdrawals, read from a TOML fee schedule.
+///
+/// A withdrawal pays the fee of the tier with the highest `from` not above its amount: `flat`
+/// plus `percent` of the amount, kept between the tier's `min` and `max`. Withdrawals below
+/// every tier are free.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeSchedule {
+    /// The client account fees are credited to.
+    pub house_account: ClientId,
+    #[serde(rename = "tier")]
+    tiers: Vec<FeeTier>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeeTier {
+    #[serde(default)]
+    from: Decimal,
+    #[serde(default)]
+    flat: Decimal,
+    #[serde(default)]
+    percent: Decimal,
+    min: Option<Decimal>,
+    max: Option<Decimal>,
+}
+
+/// A fee charged on a withdrawal and credited to the house account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fee {
+    pub house: ClientId,
+    pub amount: Amount,
+}
+
+impl FeeSchedule {
+    /// Reads and checks a TOML fee schedule.
+    pub fn load(filename: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(filename)
+            .with_context(|| format!("failed to read fee schedule: {filename}"))?;
+        let mut schedule: FeeSchedule = toml::from_str(&content)
+            .with_context(|| format!("invalid fee schedule: {filename}"))?;
+        schedule
+            .check()
+            .with_context(|| format!("invalid fee schedule: {filename}"))?;
+        schedule.tiers.sort_by_key(|tier| tier.from);
+        Ok(schedule)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.tiers.is_empty() {
+            bail!("no fee tiers");
+        }
+        for tier in &self.tiers {
+            let from = tier.from;
+            let amounts = [
+                Some(from),
+                Some(tier.flat),
+                Some(tier.percent),
+                tier.min,
+                tier.max,
+            ];
+            if amounts
+                .into_iter()
+                .flatten()
+                .any(|amount| amount.is_sign_negative())
+            {
+                bail!("tier from {from}: negative amount");
+            }
+            if tier.percent > Decimal::ONE_HUNDRED {
+                bail!("tier from {from}: percent above 100");
+            }
+            if let (Some(min), Some(max)) = (tier.min, tier.max)
+                && min > max
+            {
+                bail!("tier from {from}: min {min} above max {max}");
+            }
+            if self.tiers.iter().filter(|other| other.from == from).count() > 1 {
+                bail!("duplicate tier from {from}");
+            }
+        }
+        Ok(())
+    }
+
+    /// The fee for withdrawing `amount`, rounded half up to `scale` decimal places, or `None`
+    /// if it's out of range.
+    #[must_use]
+    pub fn fee(&self, amount: Amount, scale: u32) -> Option<Amount> {
+        let amount = amount.to_decimal();
+        let Some(tier) = self.tiers.iter().rev().find(|tier| tier.from <= amount) else {
+            return Some(Amount::ZERO);
+        };
+        let mut fee = tier
+            .flat
+            .checked_add(amount.checked_mul(tier.percent)? / Decimal::ONE_HUNDRED)?;
+        if let Some(min) = tier.min {
+            fee = fee.max(min);
+        }
+        if let Some(max) = tier.max {
+            fee = fee.min(max);
+        }
+        Amount::try_from(fee.round_dp_with_strategy(scale, RoundingStrategy::MidpointAwayFromZero))
+            .ok()
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::engine::{ClientSnapshot, Step};
+use crate::rejection::Rejection;
+use crate::transaction::{ClientId, TransactionId, TransactionType};
+
+/// A transaction that was applied to or rejected for a client, with the balances around it.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub line: u64,
+    pub tx_type: TransactionType,
+    pub tx: TransactionId,
+    pub amount: Option<Amount>,
+    pub rejection: Option<Rejection>,
+    pub before: Option<ClientSnapshot>,
+    pub after: Option<ClientSnapshot>,
+    /// The client whose transfer or withdrawal fee credited this client with `amount`, if the
+    /// entry is for the destination of a transfer or the house account.
+    pub credited_by: Option<ClientId>,
+}
+
+impl HistoryEntry {
+    /// The label of the entry's transaction type, or `fee` for a fee the house account
+    /// received.
+    #[must_use]
+    pub fn operation(&self) -> &'static str {
+        if self.credited_by.is_some() && self.tx_type == TransactionType::Withdrawal {
+            "fee"
+        } else {
+            self.tx_type.label()
+        }
+    }
+}
+
+/// Per-client audit trail of every processed transaction.
+#[derive(Debug, Default)]
+pub struct History {
+    only_client: Option<ClientId>,
+    entries: HashMap<ClientId, Vec<HistoryEntry>>,
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryRecord {
+    line: u64,
+    #[serde(rename = "type")]
+    tx_type: &'static str,
+    tx: TransactionId,
+    amount: Option<Amount>,
+    status: &'static str,
+    reason: Option<String>,
+    available: Option<Amount>,
+    held: Option<Amount>,
+    total: Option<Amount>,
+    locked: Option<bool>,
+}
+
+impl History {
+    /// Records the history of all clients.
+    #[must_use]
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    /// Records the history of a single client only.
+    #[must_use]
+    pub fn for_client(client: ClientId) -> Self {
+        History {
+            only_client: Some(client),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Records a step for its client and, if it credited another client, for the destination
+    /// of the transfer or the house account paid a fee.
+    pub fn record(&mut self, step: &Step) {
+        self.push(
+            step.client,
+            HistoryEntry {
+                line: step.line,
+                tx_type: step.tx_type,
+                tx: step.tx,
+                amount: step.amount,
+                rejection: step.result.clone().err(),
+                before: step.before,
+                after: step.after,
+                credited_by: None,
+            },
+        );
+        if let Some(counterparty) = step.counterparty {
+            self.push(
+                counterparty.client,
+                HistoryEntry {
+                    line: step.line,
+                    tx_type: step.tx_type,
+                    tx: step.tx,
+                    amount: Some(counterparty.amount),
+                    rejection: None,
+                    before: counterparty.before,
+                    after: counterparty.after,
+                    credited_by: Some(step.client),
+                },
+            );
+        }
+    }
+
+    fn push(&mut self, client: ClientId, entry: HistoryEntry) {
+        if self.only_client.is_none_or(|only| only == client) {
+            self.entries.entry(client).or_default().push(entry);
+        }
+    }
+
+    /// All recorded entries of a client, in input order.
+    #[must_use]
+    pub fn entries(&self, client: ClientId) -> &[HistoryEntry] {
+        self.entries.get(&client).map_or(&[], Vec::as_slice)
+    }
+
+    /// Writes the entries of a client as CSV.
+    pub fn write_csv<W: std::io::Write>(&self, client: ClientId, writer: W) -> Result<()> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        for entry in self.entries(client) {
+            csv_writer.serialize(HistoryRecord {
+                line: entry.line,
+                tx_type: entry.operation(),
+                tx: entry.tx,
+                amount: entry.amount,
+                status: if entry.rejection.is_some() {
+                    "rejected"
+                } else {
+                    "applied"
+                },
+                reason: entry.rejection.as_ref().map(ToString::to_string),
+                available: entry.after.map(|after| after.available),
+                held: entry.after.map(|after| after.held),
+                total: entry.after.map(|after| after.total()),
+                locked: entry.after.map(|after| after.locked),
+            })?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::de::DeserializeOwned;
+
+use crate::transaction::TransactionRecord;
+
+/// Opens a CSV file, or STDIN if `filename` is `-`.
+pub fn csv_reader(filename: &str) -> Result<csv::Reader<Box<dyn std::io::Read>>> {
+    let reader: Box<dyn std::io::Read> = if filename == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(std::fs::File::open(filename)?)
+    };
+
+    Ok(csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader))
+}
+
+/// Deserializes each record together with the line it starts on.
+pub fn records_with_lines<T, R>(
+    reader: &mut csv::Reader<R>,
+) -> Result<impl Iterator<Item = Result<(u64, T)>> + '_>
+where
+    T: DeserializeOwned,
+    R: std::io::Read,
+{
+    let headers = reader.headers()?.clone();
+    Ok(reader.records().map(move |result| {
+        let raw = result?;
+        let line = raw.position().map_or(0, csv::Position::line);
+        let record = raw
+            .deserialize(Some(&headers))
+            .with_context(|| format!("line {line}"))?;
+        Ok((line, record))
+    }))
+}
+
+/// Reads transaction rows with their line numbers, logging and skipping rows that can't be
+/// parsed.
+pub fn transaction_rows<R: std::io::Read>(
+    reader: &mut csv::Reader<R>,
+) -> Result<impl Iterator<Item = (u64, TransactionRecord)> + '_> {
+    Ok(records_with_lines(reader)?.filter_map(|result| {
+        result
+            .inspect_err(|e| warn!("failed to read record: {e:#}"))
+            .ok()
+    }))
+}
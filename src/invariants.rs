@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+use crate::amount::Amount;
+use crate::engine::{ClientState, Ledger, Step};
+use crate::transaction::{ClientId, TransactionType};
+
+/// Checks that processing kept every client's balances consistent with its transactions.
+///
+/// Record every step with [`InvariantChecker::record`], then call
+/// [`InvariantChecker::violations`] with the final ledger.
+#[derive(Debug, Default)]
+pub struct InvariantChecker {
+    clients: HashMap<ClientId, Flows>,
+    violations: Vec<Violation>,
+}
+
+/// What moved in and out of a client's account, in ten-thousandths. Sums are kept in `i128`
+/// so that they can't overflow over a long input.
+#[derive(Debug, Default)]
+struct Flows {
+    opening_total: i128,
+    opening_debt: i128,
+    /// Part of the opening held balance not backed by an open deposit under dispute.
+    untracked_held: i128,
+    deposits: i128,
+    withdrawals: i128,
+    /// Withdrawal fees paid, less the fees received as the house account.
+    fees: i128,
+    /// Transfers to other clients, less the transfers received from them.
+    transfers: i128,
+    chargebacks: i128,
+    locked_before: bool,
+    charged_back: bool,
+}
+
+/// A broken ledger invariant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// `held` doesn't match the held parts of the deposits currently under dispute.
+    Held {
+        client: ClientId,
+        held: Decimal,
+        expected: Decimal,
+    },
+    /// `total` doesn't match opening balance plus deposits minus withdrawals, fees, net
+    /// transfers and chargebacks, adjusted for debt.
+    Total {
+        client: ClientId,
+        total: Decimal,
+        expected: Decimal,
+    },
+    /// `held` went negative, after the row on `line` or in the final ledger.
+    NegativeHeld {
+        client: ClientId,
+        line: Option<u64>,
+        held: Decimal,
+    },
+    /// The account is locked without a chargeback.
+    LockedWithoutChargeback { client: ClientId },
+}
+
+impl Violation {
+    #[must_use]
+    pub fn client(&self) -> ClientId {
+        match self {
+            Violation::Held { client, .. }
+            | Violation::Total { client, .. }
+            | Violation::NegativeHeld { client, .. }
+            | Violation::LockedWithoutChargeback { client } => *client,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Held {
+                client,
+                held,
+                expected,
+            } => write!(
+                f,
+                "client {}: held {held}, expected {expected} under dispute (diff {})",
+                client.0,
+                held - expected
+            ),
+            Violation::Total {
+                client,
+                total,
+                expected,
+            } => write!(
+                f,
+                "client {}: total {total}, expected {expected} from opening balance, deposits, \
+                 withdrawals, transfers and chargebacks (diff {})",
+                client.0,
+                total - expected
+            ),
+            Violation::NegativeHeld {
+                client,
+                line: Some(line),
+                held,
+            } => write!(
+                f,
+                "client {}: held {held} is negative after line {line}",
+                client.0
+            ),
+            Violation::NegativeHeld {
+                client,
+                line: None,
+                held,
+            } => write!(f, "client {}: held {held} is negative", client.0),
+            Violation::LockedWithoutChargeback { client } => {
+                write!(f, "client {}: locked without a chargeback", client.0)
+            }
+        }
+    }
+}
+
+impl InvariantChecker {
+    /// Starts from the balances and open deposits in `ledger`. Accounts locked there are
+    /// assumed to have had a chargeback in an earlier run.
+    #[must_use]
+    pub fn new(ledger: &Ledger) -> Self {
+        let clients = ledger
+            .iter()
+            .map(|(&client, state)| {
+                let flows = Flows {
+                    opening_total: i128::from(state.total().units()),
+                    opening_debt: i128::from(state.debt.units()),
+                    untracked_held: i128::from(state.held.units()) - held_units(state),
+                    locked_before: state.locked,
+                    ..Flows::default()
+                };
+                (client, flows)
+            })
+            .collect();
+        InvariantChecker {
+            clients,
+            violations: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, step: &Step) {
+        if let Some(after) = step.after
+            && after.held.is_negative()
+        {
+            self.violations.push(Violation::NegativeHeld {
+                client: step.client,
+                line: Some(step.line),
+                held: after.held.to_decimal(),
+            });
+        }
+        let (Ok(()), Some(amount)) = (&step.result, step.amount) else {
+            return;
+        };
+        if let Some(fee) = step.fee {
+            let fee_units = i128::from(fee.amount.units());
+            self.clients.entry(step.client).or_default().fees += fee_units;
+            self.clients.entry(fee.house).or_default().fees -= fee_units;
+        }
+        let amount = i128::from(amount.units());
+        if let Some(destination) = step.destination {
+            self.clients.entry(destination).or_default().transfers -= amount;
+        }
+        let flows = self.clients.entry(step.client).or_default();
+        match step.tx_type {
+            TransactionType::Deposit => flows.deposits += amount,
+            TransactionType::Withdrawal => flows.withdrawals += amount,
+            TransactionType::Transfer => flows.transfers += amount,
+            TransactionType::Chargeback => {
+                flows.chargebacks += amount;
+                flows.charged_back = true;
+            }
+            TransactionType::Dispute | TransactionType::Resolve => {}
+        }
+    }
+
+    /// All violations found while recording and in the final `ledger`, ordered by client.
+    #[must_use]
+    pub fn violations(&self, ledger: &Ledger) -> Vec<Violation> {
+        let mut violations = self.violations.clone();
+        let no_flows = Flows::default();
+        for (&client, state) in ledger {
+            let flows = self.clients.get(&client).unwrap_or(&no_flows);
+
+            let expected_held = flows.untracked_held + held_units(state);
+            if i128::from(state.held.units()) != expected_held {
+                violations.push(Violation::Held {
+                    client,
+                    held: state.held.to_decimal(),
+                    expected: to_decimal(expected_held),
+                });
+            }
+            // Debt is what the client owes on top of its balances: it grows by the part of a
+            // dispute that couldn't be held and shrinks as deposits and transfers pay it off.
+            let expected_total = flows.opening_total - flows.opening_debt + flows.deposits
+                - flows.withdrawals
+                - flows.fees
+                - flows.transfers
+                - flows.chargebacks
+                - shortfall_units(state)
+                + i128::from(state.debt.units());
+            if i128::from(state.total().units()) != expected_total {
+                violations.push(Violation::Total {
+                    client,
+                    total: state.total().to_decimal(),
+                    expected: to_decimal(expected_total),
+                });
+            }
+            if state.held.is_negative() {
+                violations.push(Violation::NegativeHeld {
+                    client,
+                    line: None,
+                    held: state.held.to_decimal(),
+                });
+            }
+            if state.locked && !flows.locked_before && !flows.charged_back {
+                violations.push(Violation::LockedWithoutChargeback { client });
+            }
+        }
+        violations.sort_by_key(|violation| violation.client().0);
+        violations
+    }
+}
+
+/// Sum of the held parts of the deposits under dispute.
+fn held_units(state: &ClientState) -> i128 {
+    state
+        .deposits
+        .values()
+        .filter(|deposit| deposit.under_dispute)
+        .map(|deposit| i128::from(deposit.held.units()))
+        .sum()
+}
+
+/// Sum of the parts of the deposits under dispute that couldn't be held.
+fn shortfall_units(state: &ClientState) -> i128 {
+    state
+        .deposits
+        .values()
+        .filter(|deposit| deposit.under_dispute)
+        .map(|deposit| i128::from(deposit.amount.units()) - i128::from(deposit.held.units()))
+        .sum()
+}
+
+fn to_decimal(units: i128) -> Decimal {
+    Decimal::from_i128_with_scale(units, Amount::SCALE)
+}
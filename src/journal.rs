@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+
+use anyhow::{Result, bail};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::engine::{ClientState, Ledger, Step};
+use crate::transaction::{ClientId, TransactionId, TransactionType};
+
+/// General ledger account money moves between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Account {
+    /// Money received from deposits and paid out by withdrawals.
+    Cash,
+    /// Money returned to card holders through chargebacks.
+    Chargebacks,
+    /// A client's available balance.
+    Available(ClientId),
+    /// A client's held balance.
+    Held(ClientId),
+    /// What a client owes for disputes it couldn't cover, under the debt shortfall policy.
+    Debt(ClientId),
+    /// The part of a client's disputed deposits that couldn't be held, until the dispute is
+    /// resolved or charged back.
+    Shortfall(ClientId),
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Account::Cash => write!(f, "cash"),
+            Account::Chargebacks => write!(f, "chargebacks"),
+            Account::Available(client) => write!(f, "client:{}:available", client.0),
+            Account::Held(client) => write!(f, "client:{}:held", client.0),
+            Account::Debt(client) => write!(f, "client:{}:debt", client.0),
+            Account::Shortfall(client) => write!(f, "client:{}:shortfall", client.0),
+        }
+    }
+}
+
+/// Double-entry form of an applied transaction: `amount` is debited from one account and
+/// credited to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub debit: Account,
+    pub credit: Account,
+    pub amount: Amount,
+}
+
+impl JournalEntry {
+    /// The entries of an applied step, none if it was rejected.
+    ///
+    /// Most steps have a single entry. Under the debt shortfall policy, the part of a deposit
+    /// or transfer paying off debt, and the part of a dispute that couldn't be held, get their
+    /// own entries.
+    #[must_use]
+    pub fn from_step(step: &Step) -> Vec<Self> {
+        let (Ok(()), Some(amount)) = (&step.result, step.amount) else {
+            return Vec::new();
+        };
+        let client = step.client;
+        let available = Account::Available(client);
+        let held = Account::Held(client);
+        let debt = Account::Debt(client);
+        let shortfall = Account::Shortfall(client);
+        // How the step changed the client's balances, from which the held and debt parts of
+        // the amount follow.
+        let before = step.before.unwrap_or_default();
+        let after = step.after.unwrap_or_default();
+        let available_change = after.available.units() - before.available.units();
+        let held_change = after.held.units() - before.held.units();
+        let part = Amount::from_units;
+        let postings = match step.tx_type {
+            TransactionType::Deposit => vec![
+                (Account::Cash, available, part(available_change)),
+                (Account::Cash, debt, part(amount.units() - available_change)),
+            ],
+            TransactionType::Withdrawal => vec![(available, Account::Cash, amount)],
+            TransactionType::Dispute => vec![
+                (available, held, part(held_change)),
+                (debt, shortfall, part(amount.units() - held_change)),
+            ],
+            TransactionType::Resolve => {
+                let returned = available_change + held_change;
+                vec![
+                    (held, available, part(-held_change)),
+                    (shortfall, available, part(returned)),
+                    (
+                        shortfall,
+                        debt,
+                        part(amount.units() + held_change - returned),
+                    ),
+                ]
+            }
+            TransactionType::Chargeback => vec![
+                (held, Account::Chargebacks, part(-held_change)),
+                (
+                    shortfall,
+                    Account::Chargebacks,
+                    part(amount.units() + held_change),
+                ),
+            ],
+            TransactionType::Transfer => match step.counterparty {
+                Some(destination) => {
+                    // Like a deposit, the transfer pays off the destination's debt first.
+                    let credited = destination.after.unwrap_or_default().available.units()
+                        - destination.before.unwrap_or_default().available.units();
+                    vec![
+                        (
+                            available,
+                            Account::Available(destination.client),
+                            part(credited),
+                        ),
+                        (
+                            available,
+                            Account::Debt(destination.client),
+                            part(amount.units() - credited),
+                        ),
+                    ]
+                }
+                None => Vec::new(),
+            },
+        };
+        let entry = |&(debit, credit, amount): &(Account, Account, Amount)| JournalEntry {
+            debit,
+            credit,
+            amount,
+        };
+        // Parts that are zero are left out, but a step of zero still gets its first entry.
+        let mut entries: Vec<_> = postings
+            .iter()
+            .filter(|&&(_, _, amount)| amount != Amount::ZERO)
+            .map(entry)
+            .collect();
+        if entries.is_empty() {
+            entries.extend(postings.first().map(entry));
+        }
+        entries
+    }
+
+    /// The entry moving the fee of an applied withdrawal to the house account, if it had one.
+    #[must_use]
+    pub fn fee_from_step(step: &Step) -> Option<Self> {
+        let fee = step.fee?;
+        Some(JournalEntry {
+            debit: Account::Available(step.client),
+            credit: Account::Available(fee.house),
+            amount: fee.amount,
+        })
+    }
+}
+
+/// Writes the journal lines of every applied transaction as CSV, keeping track of the net
+/// postings to each client account.
+#[derive(Debug)]
+pub struct JournalWriter<W: Write> {
+    writer: csv::Writer<W>,
+    /// Opening balance plus credits less debits of each client account, in ten-thousandths.
+    /// Debt, being owed to the house, counts as a negative balance.
+    balances: HashMap<Account, i128>,
+}
+
+#[derive(Debug, Serialize)]
+struct JournalRecord {
+    line: u64,
+    tx: TransactionId,
+    operation: &'static str,
+    account: String,
+    debit: Option<Amount>,
+    credit: Option<Amount>,
+}
+
+impl<W: Write> JournalWriter<W> {
+    /// Starts the client accounts from their balances in `ledger`.
+    pub fn new(writer: W, ledger: &Ledger) -> Self {
+        let balances = ledger
+            .iter()
+            .flat_map(|(&client, state)| client_balances(client, state))
+            .collect();
+        JournalWriter {
+            writer: csv::Writer::from_writer(writer),
+            balances,
+        }
+    }
+
+    /// Writes the debit and credit lines of an applied step, followed by those of its fee.
+    /// Rejected steps are skipped.
+    pub fn write(&mut self, step: &Step) -> Result<()> {
+        for entry in JournalEntry::from_step(step) {
+            self.write_entry(step, step.tx_type.label(), entry)?;
+        }
+        if let Some(entry) = JournalEntry::fee_from_step(step) {
+            self.write_entry(step, "fee", entry)?;
+        }
+        Ok(())
+    }
+
+    fn write_entry(
+        &mut self,
+        step: &Step,
+        operation: &'static str,
+        entry: JournalEntry,
+    ) -> Result<()> {
+        let record = |account: Account, debit, credit| JournalRecord {
+            line: step.line,
+            tx: step.tx,
+            operation,
+            account: account.to_string(),
+            debit,
+            credit,
+        };
+        self.writer
+            .serialize(record(entry.debit, Some(entry.amount), None))?;
+        self.writer
+            .serialize(record(entry.credit, None, Some(entry.amount)))?;
+        let units = i128::from(entry.amount.units());
+        *self.balances.entry(entry.debit).or_default() -= units;
+        *self.balances.entry(entry.credit).or_default() += units;
+        Ok(())
+    }
+
+    /// Flushes the journal and checks that the postings to every client account add up to
+    /// its balance in the final `ledger`.
+    pub fn finish(mut self, ledger: &Ledger) -> Result<()> {
+        self.writer.flush()?;
+        let mut mismatches = Vec::new();
+        for (&client, state) in ledger {
+            for (account, balance) in client_balances(client, state) {
+                let posted = self.balances.get(&account).copied().unwrap_or_default();
+                if posted != balance {
+                    mismatches.push(format!(
+                        "  {account}: journal {}, ledger {}",
+                        to_decimal(posted),
+                        to_decimal(balance)
+                    ));
+                }
+            }
+        }
+        if !mismatches.is_empty() {
+            mismatches.sort();
+            bail!(
+                "journal doesn't match the ledger:\n{}",
+                mismatches.join("\n")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The balances of a client's accounts, as credits less debits in ten-thousandths.
+fn client_balances(client: ClientId, state: &ClientState) -> [(Account, i128); 3] {
+    [
+        (Account::Available(client), state.available.units().into()),
+        (Account::Held(client), state.held.units().into()),
+        (Account::Debt(client), (-state.debt).units().into()),
+    ]
+}
+
+fn to_decimal(units: i128) -> Decimal {
+    Decimal::from_i128_with_scale(units, Amount::SCALE)
+}
@@ -0,0 +1,29 @@
+//! Transactions processing engine: parses input rows, applies them to client accounts and
+//! reports the resulting balances.
+
+pub mod access;
+pub mod alerts;
+pub mod amount;
+pub mod consolidated;
+pub mod diff;
+pub mod disputes;
+pub mod engine;
+pub mod events;
+pub mod explain;
+pub mod fees;
+pub mod history;
+pub mod input;
+pub mod invariants;
+pub mod journal;
+pub mod limits;
+pub mod metadata;
+pub mod opening;
+pub mod point_in_time;
+pub mod policy;
+pub mod precision;
+pub mod rejection;
+pub mod risk;
+pub mod transaction;
+pub mod validate;
+pub mod velocity;
+pub mod what_if;
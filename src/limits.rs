@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::amount::Amount;
+use crate::input::{csv_reader, records_with_lines};
+use crate::rejection::Rejection;
+use crate::transaction::ClientId;
+
+/// Optional upper bounds on transaction amounts and client balances.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub max_amount: Option<Amount>,
+    pub max_balance: Option<Amount>,
+}
+
+impl Limits {
+    pub fn check_amount(&self, amount: Amount) -> Result<(), Rejection> {
+        match self.max_amount {
+            Some(limit) if amount > limit => Err(Rejection::AmountLimit { amount, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_balance(&self, client: ClientId, balance: Amount) -> Result<(), Rejection> {
+        match self.max_balance {
+            Some(limit) if balance > limit => Err(Rejection::BalanceLimit {
+                client,
+                balance,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Approved overdraft lines of clients that may withdraw more than their available balance.
+pub type OverdraftLimits = HashMap<ClientId, OverdraftLimit>;
+
+/// How far a client's available balance may go below zero through withdrawals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OverdraftLimit {
+    pub allowance: Amount,
+    /// Largest amount of a single withdrawal, if limited.
+    pub max_withdrawal: Option<Amount>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OverdraftLimitRecord {
+    client: ClientId,
+    allowance: Amount,
+    #[serde(default)]
+    max_withdrawal: Option<Amount>,
+}
+
+impl OverdraftLimit {
+    /// Checks that a withdrawal debiting `debit` in total (`amount` plus any fee) stays within
+    /// the allowance, given the client's `available` balance.
+    pub fn check_withdrawal(
+        &self,
+        client: ClientId,
+        available: Amount,
+        amount: Amount,
+        debit: Amount,
+    ) -> Result<(), Rejection> {
+        if let Some(limit) = self.max_withdrawal
+            && amount > limit
+        {
+            return Err(Rejection::AmountLimit { amount, limit });
+        }
+        // Compared in `i128` as available plus the allowance may not fit in an `Amount`.
+        let spendable = i128::from(available.units()) + i128::from(self.allowance.units());
+        if spendable < i128::from(debit.units()) {
+            return Err(Rejection::InsufficientFunds {
+                client,
+                available,
+                requested: debit,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Reads overdraft limits given as `client,allowance,max_withdrawal` rows, where
+/// `max_withdrawal` is optional.
+pub fn load_overdraft_limits(filename: &str) -> Result<OverdraftLimits> {
+    let mut reader = csv_reader(filename)
+        .with_context(|| format!("failed to open overdraft limits: {filename}"))?;
+    let mut limits = OverdraftLimits::new();
+    for result in records_with_lines::<OverdraftLimitRecord, _>(&mut reader)? {
+        let (line, record) = result.context("failed to read overdraft limits")?;
+        let client = record.client;
+        if record.allowance.is_negative() {
+            bail!("overdraft limits line {line}: negative allowance for {client:?}");
+        }
+        if record.max_withdrawal.is_some_and(Amount::is_negative) {
+            bail!("overdraft limits line {line}: negative max withdrawal for {client:?}");
+        }
+        let limit = OverdraftLimit {
+            allowance: record.allowance,
+            max_withdrawal: record.max_withdrawal,
+        };
+        if limits.insert(client, limit).is_some() {
+            bail!("overdraft limits line {line}: duplicate {client:?}");
+        }
+    }
+    Ok(limits)
+}
@@ -1,298 +1,397 @@
-use std::collections::{HashMap, hash_map::Entry};
+use std::fs::File;
+use std::io::LineWriter;
+use std::process::ExitCode;
 
-use anyhow::{Context, Result, anyhow, bail};
+use anyhow::{Context, Result, bail};
 use log::warn;
-
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
 
-fn main() -> Result<()> {
+use yet_another_transactions_processor::alerts::AlertMonitor;
+use yet_another_transactions_processor::consolidated::ConsolidatedReport;
+use yet_another_transactions_processor::diff;
+use yet_another_transactions_processor::disputes::OpenDisputes;
+use yet_another_transactions_processor::engine::{ClientState, Ledger, Processor, Step};
+use yet_another_transactions_processor::events::EventWriter;
+use yet_another_transactions_processor::explain::Explanation;
+use yet_another_transactions_processor::history::History;
+use yet_another_transactions_processor::input::{csv_reader, transaction_rows};
+use yet_another_transactions_processor::invariants::InvariantChecker;
+use yet_another_transactions_processor::journal::JournalWriter;
+use yet_another_transactions_processor::metadata::RunMetadata;
+use yet_another_transactions_processor::opening;
+use yet_another_transactions_processor::point_in_time::SnapshotWriter;
+use yet_another_transactions_processor::precision::{Precision, PrecisionMode};
+use yet_another_transactions_processor::risk::{RiskReport, RiskThresholds};
+use yet_another_transactions_processor::validate;
+use yet_another_transactions_processor::what_if::{self, Rejections, WhatIfReport};
+
+use cli::{Command, OutputOptions, ProcessOptions};
+
+mod cli;
+
+fn main() -> Result<ExitCode> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("error")).init();
-    let input_filename = std::env::args().nth(1).context("no input file specified")?;
-    let mut csv_reader = csv_reader(&input_filename)?;
-
-    let mut ledger = Ledger::new();
-    for result in csv_reader.deserialize() {
-        let record = match result {
-            Ok(record) => record,
-            Err(e) => {
-                warn!("failed to read record: {e}");
-                continue;
-            }
-        };
-        let transaction = match Transaction::try_from(&record) {
-            Ok(transaction) => transaction,
-            Err(e) => {
-                warn!("failed to parse record: {record:?}: {e}");
-                continue;
+    match cli::parse_args(std::env::args().skip(1))? {
+        Command::Process(options) => process(&options)?,
+        Command::Validate {
+            input,
+            examples,
+            max_scale,
+        } => {
+            let precision = Precision::new(max_scale, PrecisionMode::Reject)?;
+            let report = validate::validate(csv_reader(&input)?, precision, examples)?;
+            print!("{report}");
+            let problems = report.problem_count();
+            if problems > 0 {
+                bail!("validation failed with {problems} problems");
             }
-        };
-        if let Err(e) = process_transaction(&mut ledger, transaction) {
-            warn!("failed to process transaction: {e}");
+        }
+        Command::Diff {
+            left,
+            right,
+            tolerance,
+        } => return Ok(diff_accounts(&left, &right, tolerance)),
+        Command::WhatIf {
+            options,
+            alternative,
+        } => {
+            let mut baseline_rejections = Rejections::new();
+            let baseline = replay(&options, None, |step| baseline_rejections.record(step))?;
+            let alternative_options = ProcessOptions {
+                policy: alternative,
+                outputs: OutputOptions::default(),
+                ..options
+            };
+            let mut alternative_rejections = Rejections::new();
+            let alternative = replay(&alternative_options, None, |step| {
+                alternative_rejections.record(step);
+            })?;
+
+            let report = WhatIfReport {
+                balances: diff::compare(
+                    &baseline.client_records().collect::<Vec<_>>(),
+                    &alternative.client_records().collect::<Vec<_>>(),
+                    Decimal::ZERO,
+                )?,
+                outcomes: what_if::compare_outcomes(&baseline_rejections, &alternative_rejections),
+            };
+            print!("{report}");
+        }
+        Command::History { client, options } => {
+            let mut history = History::for_client(client);
+            replay(&options, Some("history"), |step| history.record(step))?;
+            history.write_csv(client, std::io::stdout())?;
+        }
+        Command::Explain { client, options } => {
+            let mut history = History::for_client(client);
+            let processor = replay(&options, Some("explain"), |step| history.record(step))?;
+            let entries = history.entries(client);
+            // Without transactions the client's final balance is its opening balance.
+            let opening = match entries.first() {
+                Some(entry) => entry.before,
+                None => processor.ledger().get(&client).map(ClientState::snapshot),
+            };
+            print!("{}", Explanation::new(client, opening, entries));
         }
     }
-
-    let mut csv_writer = csv::Writer::from_writer(std::io::stdout());
-    for (client_id, client) in ledger {
-        let client_record = client.to_client_record(client_id);
-        csv_writer.serialize(client_record)?;
-    }
-
-    Ok(())
-}
-
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Serialize)]
-struct ClientId(u16);
-
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Serialize)]
-struct TransactionId(u32);
-
-#[derive(Debug, Clone, Copy)]
-enum Transaction {
-    Deposit {
-        client: ClientId,
-        tx: TransactionId,
-        amount: Decimal,
-    },
-    Withdrawal {
-        client: ClientId,
-        amount: Decimal,
-    },
-    Dispute {
-        client: ClientId,
-        tx: TransactionId,
-    },
-    Resolve {
-        client: ClientId,
-        tx: TransactionId,
-    },
-    Chargeback {
-        client: ClientId,
-        tx: TransactionId,
-    },
+    Ok(ExitCode::SUCCESS)
 }
 
-impl TryFrom<&TransactionRecord> for Transaction {
-    type Error = anyhow::Error;
-
-    fn try_from(record: &TransactionRecord) -> Result<Self, Self::Error> {
-        let client = record.client;
-        let tx = record.tx;
-        match record.tx_type {
-            TransactionType::Deposit => {
-                let amount = record.validated_amount()?;
-                Ok(Transaction::Deposit { client, tx, amount })
-            }
-            TransactionType::Withdrawal => {
-                let amount = record.validated_amount()?;
-                Ok(Transaction::Withdrawal { client, amount })
+/// Like diff(1), exits with 1 if the accounts differ and with 2 if they can't be compared.
+fn diff_accounts(left: &str, right: &str, tolerance: Decimal) -> ExitCode {
+    let compared = diff::read_accounts(left).and_then(|left| {
+        diff::read_accounts(right).and_then(|right| diff::compare(&left, &right, tolerance))
+    });
+    match compared {
+        Ok(diff) => {
+            print!("{diff}");
+            if diff.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(1)
             }
-            TransactionType::Dispute => Ok(Transaction::Dispute { client, tx }),
-            TransactionType::Resolve => Ok(Transaction::Resolve { client, tx }),
-            TransactionType::Chargeback => Ok(Transaction::Chargeback { client, tx }),
         }
-    }
-}
-
-#[derive(Debug, Deserialize)]
-struct TransactionRecord {
-    #[serde(rename = "type")]
-    tx_type: TransactionType,
-    client: ClientId,
-    tx: TransactionId,
-    amount: Option<Decimal>,
-}
-
-impl TransactionRecord {
-    fn validated_amount(&self) -> Result<Decimal> {
-        let amount = self.amount.ok_or_else(|| anyhow!("missing amount"))?;
-        if amount < Decimal::ZERO {
-            bail!("negative amount not allowed");
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(2)
         }
-        Ok(amount)
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
-#[serde(rename_all = "lowercase")]
-enum TransactionType {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    Chargeback,
-}
+fn process(options: &ProcessOptions) -> Result<()> {
+    let processor = replay(options, None, |_| {})?;
 
-fn process_transaction(ledger: &mut Ledger, transaction: Transaction) -> Result<()> {
-    match transaction {
-        Transaction::Deposit { client, tx, amount } => process_deposit(ledger, client, tx, amount),
-        Transaction::Withdrawal { client, amount } => process_withdrawal(ledger, client, amount),
-        Transaction::Dispute { client, tx } => process_dispute(ledger, client, tx),
-        Transaction::Resolve { client, tx } => process_resolve(ledger, client, tx),
-        Transaction::Chargeback { client, tx } => process_chargeback(ledger, client, tx),
+    let mut csv_writer = csv::Writer::from_writer(std::io::stdout());
+    for client_record in processor.client_records() {
+        csv_writer.serialize(client_record)?;
     }
-}
 
-fn process_deposit(
-    ledger: &mut Ledger,
-    client: ClientId,
-    tx: TransactionId,
-    amount: Decimal,
-) -> Result<()> {
-    let client_state = match ledger.entry(client) {
-        Entry::Occupied(entry) => {
-            let client_state = entry.into_mut();
-            if client_state.locked {
-                bail!("deposit for locked account: {client:?}");
-            }
-            if client_state.deposits.contains_key(&tx) {
-                bail!("duplicate transaction: {tx:?}");
-            }
-            client_state
-        }
-        Entry::Vacant(entry) => entry.insert(ClientState::default()),
-    };
-
-    client_state.available += amount;
-    client_state.deposits.insert(
-        tx,
-        StoredDeposit {
-            amount,
-            under_dispute: false,
-        },
-    );
     Ok(())
 }
 
-fn process_withdrawal(ledger: &mut Ledger, client: ClientId, amount: Decimal) -> Result<()> {
-    let Some(client_state) = ledger.get_mut(&client) else {
-        bail!("withdrawal for non existing account: {client:?}");
+/// Runs the whole input through a processor, handing every processed row to `observe`.
+/// `command` is the subcommand, if it assumes a single balance per client.
+fn replay(
+    options: &ProcessOptions,
+    command: Option<&str>,
+    mut observe: impl FnMut(&Step),
+) -> Result<Processor> {
+    let mut ledger = match &options.opening_balances {
+        Some(filename) => opening::load_opening_balances(filename, options.precision)?,
+        None => Ledger::new(),
     };
-    client_state.check_unlocked("withdrawal", client)?;
-    if client_state.available < amount {
-        bail!(
-            "insufficient funds (available: {}, requested: {amount}): {client:?}",
-            client_state.available
-        );
+    if let Some(filename) = &options.open_deposits {
+        opening::load_open_deposits(&mut ledger, filename, options.precision)?;
     }
-
-    client_state.available -= amount;
-    Ok(())
-}
-
-fn process_dispute(ledger: &mut Ledger, client: ClientId, tx: TransactionId) -> Result<()> {
-    let Some(client_state) = ledger.get_mut(&client) else {
-        bail!("dispute for non existing account: {client:?}");
-    };
-    client_state.check_unlocked("dispute", client)?;
-    let deposit = client_state.get_deposit_mut(tx, "dispute")?;
-    if deposit.under_dispute {
-        bail!("transaction already under dispute: {tx:?}");
+    let mut csv_reader = csv_reader(&options.input)?;
+    let currencies = csv_reader
+        .headers()?
+        .iter()
+        .any(|header| header == "currency");
+    if currencies {
+        check_currency_support(options, command)?;
+    } else if options.outputs.consolidated.is_some() {
+        bail!("--consolidated requires an input with a currency column");
     }
-
-    let amount = deposit.amount;
-    deposit.under_dispute = true;
-    client_state.held += amount;
-    client_state.available -= amount;
-    Ok(())
-}
-
-fn process_resolve(ledger: &mut Ledger, client: ClientId, tx: TransactionId) -> Result<()> {
-    let Some(client_state) = ledger.get_mut(&client) else {
-        bail!("resolve for non existing account: {client:?}");
-    };
-    client_state.check_unlocked("resolve", client)?;
-    let deposit = client_state.get_deposit_mut(tx, "resolve")?;
-    if !deposit.under_dispute {
-        bail!("resolve for transaction not under dispute: {tx:?}");
+    let mut processor = Processor::new(ledger, options.precision, options.limits)
+        .with_policy(options.policy)
+        .with_access(options.access.clone());
+    if currencies {
+        processor = processor.with_currencies();
+    }
+    if let Some(fees) = &options.fees {
+        processor = processor.with_fees(fees.clone());
+    }
+    if let Some(overdrafts) = &options.overdrafts {
+        processor = processor.with_overdrafts(overdrafts.clone());
     }
+    if let Some(velocity) = &options.velocity {
+        processor = processor.with_velocity_limits(velocity.clone());
+    }
+    let mut outputs = Outputs::open(options, processor.ledger())?;
+
+    for (line, record) in transaction_rows(&mut csv_reader)? {
+        if options
+            .as_of
+            .is_some_and(|as_of| !as_of.includes(line, record.timestamp))
+        {
+            break;
+        }
+        outputs.before_row(&processor, line, record.timestamp)?;
+        let step = processor.process(line, &record);
+        if let Err(e) = &step.result {
+            warn!("line {line}: failed to process transaction: {e}");
+        }
+        outputs.observe(&step)?;
+        observe(&step);
+    }
+    outputs.finish(&processor)?;
 
-    let amount = deposit.amount;
-    deposit.under_dispute = false;
-    client_state.held -= amount;
-    client_state.available += amount;
-    Ok(())
+    Ok(processor)
 }
 
-fn process_chargeback(ledger: &mut Ledger, client: ClientId, tx: TransactionId) -> Result<()> {
-    let Some(client_state) = ledger.get_mut(&client) else {
-        bail!("chargeback for non existing account: {client:?}");
-    };
-    client_state.check_unlocked("chargeback", client)?;
-    let deposit = client_state.get_deposit_mut(tx, "chargeback")?;
-    if !deposit.under_dispute {
-        bail!("chargeback for transaction not under dispute: {tx:?}");
+/// Fails for the `command` and options that assume a single balance per client, which inputs
+/// with a currency column don't have.
+fn check_currency_support(options: &ProcessOptions, command: Option<&str>) -> Result<()> {
+    if let Some(command) = command {
+        bail!("{command} doesn't support inputs with a currency column");
+    }
+    let outputs = &options.outputs;
+    let unsupported = [
+        ("--opening-balances", options.opening_balances.is_some()),
+        ("--verify", outputs.verify),
+        ("--journal", outputs.journal.is_some()),
+        ("--open-disputes", outputs.open_disputes.is_some()),
+        (
+            "--open-dispute-totals",
+            outputs.open_dispute_totals.is_some(),
+        ),
+        ("--risk-report", outputs.risk_report.is_some()),
+    ];
+    if let Some((option, _)) = unsupported.iter().find(|(_, used)| *used) {
+        bail!("{option} doesn't support inputs with a currency column");
     }
-
-    let amount = deposit.amount;
-    deposit.under_dispute = false;
-    client_state.held -= amount;
-    client_state.locked = true;
     Ok(())
 }
 
-#[derive(Debug, Serialize)]
-struct ClientRecord {
-    client: ClientId,
-    available: Decimal,
-    held: Decimal,
-    total: Decimal,
-    locked: bool,
+/// Optional outputs written alongside processing, as requested in the options.
+struct Outputs {
+    events: Option<EventWriter<LineWriter<File>>>,
+    snapshots: Option<SnapshotWriter<File>>,
+    disputes: Option<DisputeReports>,
+    risk: Option<(RiskReport, RiskThresholds, File)>,
+    invariants: Option<InvariantChecker>,
+    journal: Option<JournalWriter<File>>,
+    metadata: Option<(RunMetadata, File)>,
+    alerts: Option<(AlertMonitor, File)>,
+    consolidated: Option<(ConsolidatedReport, File)>,
 }
 
-#[derive(Debug)]
-struct StoredDeposit {
-    amount: Decimal,
-    under_dispute: bool,
+/// Open disputes tracked during the run, written to either or both reports at the end.
+struct DisputeReports {
+    open_disputes: OpenDisputes,
+    list: Option<File>,
+    totals: Option<File>,
 }
 
-#[derive(Debug, Default)]
-struct ClientState {
-    deposits: HashMap<TransactionId, StoredDeposit>,
-    available: Decimal,
-    held: Decimal,
-    locked: bool,
-}
+impl Outputs {
+    fn open(options: &ProcessOptions, ledger: &Ledger) -> Result<Self> {
+        let outputs = &options.outputs;
+        let events = match &outputs.events {
+            Some(filename) => Some(EventWriter::new(LineWriter::new(create_file(filename)?))),
+            None => None,
+        };
+        let snapshots = match &outputs.snapshots {
+            Some((filename, interval)) => {
+                Some(SnapshotWriter::new(create_file(filename)?, *interval))
+            }
+            None => None,
+        };
+        let disputes = if outputs.open_disputes.is_some() || outputs.open_dispute_totals.is_some() {
+            Some(DisputeReports {
+                open_disputes: OpenDisputes::new(ledger),
+                list: outputs
+                    .open_disputes
+                    .as_deref()
+                    .map(create_file)
+                    .transpose()?,
+                totals: outputs
+                    .open_dispute_totals
+                    .as_deref()
+                    .map(create_file)
+                    .transpose()?,
+            })
+        } else {
+            None
+        };
+        let journal = match &outputs.journal {
+            Some(filename) => Some(JournalWriter::new(create_file(filename)?, ledger)),
+            None => None,
+        };
+        let risk = match &outputs.risk_report {
+            Some((filename, thresholds)) => {
+                Some((RiskReport::new(), *thresholds, create_file(filename)?))
+            }
+            None => None,
+        };
+        let metadata = match &outputs.metadata {
+            Some(filename) => Some((
+                RunMetadata::new(&options.input, options.policy),
+                create_file(filename)?,
+            )),
+            None => None,
+        };
+        let alerts = match &outputs.alerts {
+            Some((filename, rules)) => Some((AlertMonitor::new(*rules), create_file(filename)?)),
+            None => None,
+        };
+        let consolidated = match &outputs.consolidated {
+            Some((filename, consolidation)) => Some((
+                ConsolidatedReport::new(consolidation.clone()),
+                create_file(filename)?,
+            )),
+            None => None,
+        };
+        Ok(Outputs {
+            events,
+            snapshots,
+            disputes,
+            risk,
+            invariants: outputs.verify.then(|| InvariantChecker::new(ledger)),
+            journal,
+            metadata,
+            alerts,
+            consolidated,
+        })
+    }
 
-impl ClientState {
-    fn check_unlocked(&self, operation: &str, client: ClientId) -> Result<()> {
-        if self.locked {
-            bail!("{operation} for locked account: {client:?}");
+    fn before_row(
+        &mut self,
+        processor: &Processor,
+        line: u64,
+        timestamp: Option<u64>,
+    ) -> Result<()> {
+        if let Some(snapshots) = &mut self.snapshots {
+            snapshots.before_row(processor, line, timestamp)?;
         }
         Ok(())
     }
 
-    fn get_deposit_mut(
-        &mut self,
-        tx: TransactionId,
-        operation: &str,
-    ) -> Result<&mut StoredDeposit> {
-        self.deposits
-            .get_mut(&tx)
-            .ok_or_else(|| anyhow!("{operation} for non existing transaction: {tx:?}"))
+    fn observe(&mut self, step: &Step) -> Result<()> {
+        if let Some(events) = &mut self.events {
+            events.write(step)?;
+        }
+        if let Some(disputes) = &mut self.disputes {
+            disputes.open_disputes.record(step);
+        }
+        if let Some((risk, _, _)) = &mut self.risk {
+            risk.record(step);
+        }
+        if let Some(invariants) = &mut self.invariants {
+            invariants.record(step);
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.write(step)?;
+        }
+        if let Some((metadata, _)) = &mut self.metadata {
+            metadata.record(step);
+        }
+        if let Some((alerts, _)) = &mut self.alerts {
+            alerts.record(step);
+        }
+        if let Some((consolidated, _)) = &mut self.consolidated {
+            consolidated.record(step);
+        }
+        Ok(())
     }
 
-    fn to_client_record(&self, client: ClientId) -> ClientRecord {
-        ClientRecord {
-            client,
-            available: self.available,
-            held: self.held,
-            total: self.available + self.held,
-            locked: self.locked,
+    fn finish(self, processor: &Processor) -> Result<()> {
+        if let Some(snapshots) = self.snapshots {
+            snapshots.finish(processor)?;
+        }
+        if let Some(disputes) = self.disputes {
+            if let Some(file) = disputes.list {
+                disputes.open_disputes.write_csv(file)?;
+            }
+            if let Some(file) = disputes.totals {
+                disputes
+                    .open_disputes
+                    .write_totals_csv(processor.ledger(), file)?;
+            }
         }
+        if let Some((risk, thresholds, file)) = self.risk {
+            risk.write_csv(thresholds, file)?;
+        }
+        if let Some(journal) = self.journal {
+            journal.finish(processor.ledger())?;
+        }
+        if let Some((metadata, file)) = self.metadata {
+            metadata.write(processor.client_count(), file)?;
+        }
+        if let Some((alerts, file)) = self.alerts {
+            alerts.write_csv(file)?;
+        }
+        if let Some((consolidated, file)) = self.consolidated {
+            consolidated.write_csv(
+                processor.client_records(),
+                processor.precision().max_scale(),
+                file,
+            )?;
+        }
+        if let Some(invariants) = self.invariants {
+            let violations = invariants.violations(processor.ledger());
+            if !violations.is_empty() {
+                let details: Vec<String> = violations
+                    .iter()
+                    .map(|violation| format!("  {violation}"))
+                    .collect();
+                bail!("ledger invariants violated:\n{}", details.join("\n"));
+            }
+        }
+        Ok(())
     }
 }
 
-type Ledger = HashMap<ClientId, ClientState>;
-
-fn csv_reader(filename: &str) -> Result<csv::Reader<Box<dyn std::io::Read>>> {
-    let reader: Box<dyn std::io::Read> = if filename == "-" {
-        Box::new(std::io::stdin())
-    } else {
-        Box::new(std::fs::File::open(filename)?)
-    };
-
-    Ok(csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_reader(reader))
+fn create_file(filename: &str) -> Result<File> {
+    File::create(filename).with_context(|| format!("failed to create {filename}"))
 }
@@ -0,0 +1,52 @@
+use std::io::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::engine::Step;
+use crate::policy::Policy;
+use crate::rejection::Rejection;
+
+/// Summary of a run and the settings it used, written as JSON.
+#[derive(Debug, Serialize)]
+pub struct RunMetadata {
+    pub input: String,
+    pub policy: Policy,
+    pub rows: u64,
+    /// Rows rejected for any reason other than the client being blocked.
+    pub rejected: u64,
+    /// Rows of clients on the blocklist or missing from the allowlist.
+    pub blocked: u64,
+    pub clients: usize,
+}
+
+impl RunMetadata {
+    #[must_use]
+    pub fn new(input: &str, policy: Policy) -> Self {
+        RunMetadata {
+            input: input.to_string(),
+            policy,
+            rows: 0,
+            rejected: 0,
+            blocked: 0,
+            clients: 0,
+        }
+    }
+
+    pub fn record(&mut self, step: &Step) {
+        self.rows += 1;
+        match step.result {
+            Ok(()) => {}
+            Err(Rejection::Blocked { .. }) => self.blocked += 1,
+            Err(_) => self.rejected += 1,
+        }
+    }
+
+    /// Writes the metadata with the number of `clients` at the end of the run.
+    pub fn write<W: Write>(mut self, clients: usize, mut writer: W) -> Result<()> {
+        self.clients = clients;
+        serde_json::to_writer_pretty(&mut writer, &self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::amount::Amount;
+use crate::engine::{ClientState, Ledger, StoredDeposit};
+use crate::input::{csv_reader, records_with_lines};
+use crate::precision::Precision;
+use crate::transaction::{ClientId, TransactionId};
+
+/// A row of a previous run's output, used as a client's opening balance.
+#[derive(Debug, Deserialize)]
+struct OpeningBalanceRecord {
+    client: ClientId,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    locked: bool,
+    /// Only present in outputs of runs where disputes can leave a debt.
+    #[serde(default)]
+    debt: Amount,
+    /// Only present in outputs of runs with a fee schedule.
+    #[serde(default)]
+    fees: Amount,
+}
+
+/// A deposit made before the migration that may still be disputed, resolved or charged back.
+#[derive(Debug, Deserialize)]
+struct OpenDepositRecord {
+    client: ClientId,
+    tx: TransactionId,
+    amount: Amount,
+    #[serde(default)]
+    under_dispute: bool,
+}
+
+/// Builds a ledger from an accounts file in the same format the processor writes.
+///
+/// Amounts with more decimal places than `precision` allows are rejected rather than rounded,
+/// as rounding them on output would break `total = available + held`.
+pub fn load_opening_balances(filename: &str, precision: Precision) -> Result<Ledger> {
+    let mut reader = csv_reader(filename)
+        .with_context(|| format!("failed to open opening balances: {filename}"))?;
+    let mut ledger = Ledger::new();
+    for result in records_with_lines::<OpeningBalanceRecord, _>(&mut reader)? {
+        let (line, record) = result.context("failed to read opening balances")?;
+        let client = record.client;
+        if record.held.is_negative() {
+            bail!("opening balances line {line}: negative held amount for {client:?}");
+        }
+        if record.debt.is_negative() {
+            bail!("opening balances line {line}: negative debt for {client:?}");
+        }
+        if record.fees.is_negative() {
+            bail!("opening balances line {line}: negative fees for {client:?}");
+        }
+        let amounts = [
+            record.available,
+            record.held,
+            record.total,
+            record.debt,
+            record.fees,
+        ];
+        if let Some(amount) = amounts
+            .into_iter()
+            .find(|amount| precision.exceeds(amount.to_decimal()))
+        {
+            bail!(
+                "opening balances line {line}: {amount} has more than {} decimal places",
+                precision.max_scale()
+            );
+        }
+        if record.available.checked_add(record.held) != Some(record.total) {
+            bail!("opening balances line {line}: total is not available + held for {client:?}");
+        }
+        if ledger.contains_key(&client) {
+            bail!("opening balances line {line}: duplicate {client:?}");
+        }
+        ledger.insert(
+            client,
+            ClientState {
+                available: record.available,
+                held: record.held,
+                locked: record.locked,
+                debt: record.debt,
+                fees: record.fees,
+                ..ClientState::default()
+            },
+        );
+    }
+    Ok(ledger)
+}
+
+/// Adds pre-migration deposits to the opening balances so later disputes can reference them.
+///
+/// The deposits of a client that are already under dispute can't add up to more than the
+/// client's held balance, as resolving or charging them back would make held negative.
+/// Their amounts can't have more decimal places than `precision` allows either.
+pub fn load_open_deposits(ledger: &mut Ledger, filename: &str, precision: Precision) -> Result<()> {
+    let mut reader = csv_reader(filename)
+        .with_context(|| format!("failed to open open deposits: {filename}"))?;
+    let mut disputed: HashMap<ClientId, Amount> = HashMap::new();
+    for result in records_with_lines::<OpenDepositRecord, _>(&mut reader)? {
+        let (line, record) = result.context("failed to read open deposits")?;
+        let (client, tx) = (record.client, record.tx);
+        let Some(client_state) = ledger.get_mut(&client) else {
+            bail!("open deposits line {line}: {client:?} has no opening balance");
+        };
+        if record.amount.is_negative() {
+            bail!("open deposits line {line}: negative amount for {tx:?}");
+        }
+        if precision.exceeds(record.amount.to_decimal()) {
+            bail!(
+                "open deposits line {line}: {} has more than {} decimal places",
+                record.amount,
+                precision.max_scale()
+            );
+        }
+        if client_state.deposits.contains_key(&tx) {
+            bail!("open deposits line {line}: duplicate {tx:?}");
+        }
+        if record.under_dispute {
+            let total = disputed.entry(client).or_default();
+            *total = total
+                .checked_add(record.amount)
+                .filter(|total| *total <= client_state.held)
+                .with_context(|| {
+                    format!(
+                        "open deposits line {line}: disputed deposits exceed held for {client:?}"
+                    )
+                })?;
+        }
+        client_state.deposits.insert(
+            tx,
+            StoredDeposit {
+                amount: record.amount,
+                under_dispute: record.under_dispute,
+                held: if record.under_dispute {
+                    record.amount
+                } else {
+                    Amount::ZERO
+                },
+                resolved: false,
+            },
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::engine::{ClientRecord, Processor};
+use crate::input::transaction_rows;
+use crate::transaction::{ClientId, Currency};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Point in the input at which processing stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOf {
+    /// Up to and including the row on this line.
+    Line(u64),
+    /// Up to and including rows with this timestamp. Rows without a timestamp are always
+    /// included; processing stops at the first row with a later timestamp.
+    Time(u64),
+}
+
+impl AsOf {
+    /// Whether a row is still within the point in time.
+    #[must_use]
+    pub fn includes(self, line: u64, timestamp: Option<u64>) -> bool {
+        match self {
+            AsOf::Line(last) => line <= last,
+            AsOf::Time(last) => timestamp.is_none_or(|timestamp| timestamp <= last),
+        }
+    }
+}
+
+impl FromStr for AsOf {
+    type Err = anyhow::Error;
+
+    /// Parses `line:<N>` or `time:<unix seconds>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .parse()
+                .with_context(|| format!("invalid point in time: {s}"))
+        };
+        match s.split_once(':') {
+            Some(("line", line)) => Ok(AsOf::Line(parse(line)?)),
+            Some(("time", timestamp)) => Ok(AsOf::Time(parse(timestamp)?)),
+            _ => bail!("invalid point in time: {s} (expected line:<N> or time:<unix seconds>)"),
+        }
+    }
+}
+
+/// Processes rows up to `as_of` and returns the balances of all clients at that point, in no
+/// particular order.
+pub fn balances_as_of<R: Read>(
+    processor: &mut Processor,
+    reader: &mut csv::Reader<R>,
+    as_of: AsOf,
+) -> Result<Vec<ClientRecord>> {
+    for (line, record) in transaction_rows(reader)? {
+        if !as_of.includes(line, record.timestamp) {
+            break;
+        }
+        processor.process(line, &record);
+    }
+    Ok(processor.client_records().collect())
+}
+
+/// How often [`SnapshotWriter`] writes the balances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotInterval {
+    /// After every N rows.
+    Rows(u64),
+    /// At the end of every day, based on the row timestamps (UTC).
+    Day,
+}
+
+impl FromStr for SnapshotInterval {
+    type Err = anyhow::Error;
+
+    /// Parses a number of rows or `day`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "day" {
+            return Ok(SnapshotInterval::Day);
+        }
+        match s.parse() {
+            Ok(0) | Err(_) => bail!("invalid snapshot interval: {s} (expected rows or day)"),
+            Ok(rows) => Ok(SnapshotInterval::Rows(rows)),
+        }
+    }
+}
+
+/// Writes the balances of all clients as CSV at regular intervals of the input.
+///
+/// Each snapshot is labelled in the `as_of` column with `line:<N>`, the last row it includes,
+/// or with the date of the day it closes.
+#[derive(Debug)]
+pub struct SnapshotWriter<W: Write> {
+    writer: csv::Writer<W>,
+    interval: SnapshotInterval,
+    pending_rows: u64,
+    last_line: u64,
+    day: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotRecord<'a> {
+    as_of: &'a str,
+    client: ClientId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    pub fn new(writer: W, interval: SnapshotInterval) -> Self {
+        SnapshotWriter {
+            writer: csv::Writer::from_writer(writer),
+            interval,
+            pending_rows: 0,
+            last_line: 0,
+            day: None,
+        }
+    }
+
+    /// Must be called before processing each row, so that a snapshot due before it reflects
+    /// the balances without it.
+    pub fn before_row(
+        &mut self,
+        processor: &Processor,
+        line: u64,
+        timestamp: Option<u64>,
+    ) -> Result<()> {
+        match self.interval {
+            SnapshotInterval::Rows(rows) => {
+                if self.pending_rows == rows {
+                    self.write_line_snapshot(processor)?;
+                }
+            }
+            SnapshotInterval::Day => {
+                if let Some(day) = timestamp.map(|timestamp| timestamp / SECONDS_PER_DAY) {
+                    match self.day {
+                        Some(current) if day > current => {
+                            self.write_snapshot(processor, &format_day(current))?;
+                            self.day = Some(day);
+                        }
+                        Some(_) => {}
+                        None => self.day = Some(day),
+                    }
+                }
+            }
+        }
+        self.pending_rows += 1;
+        self.last_line = line;
+        Ok(())
+    }
+
+    /// Writes the snapshot of the last, possibly incomplete, interval.
+    pub fn finish(mut self, processor: &Processor) -> Result<()> {
+        match self.interval {
+            SnapshotInterval::Rows(_) if self.pending_rows > 0 => {
+                self.write_line_snapshot(processor)?;
+            }
+            SnapshotInterval::Day => {
+                if let Some(day) = self.day {
+                    self.write_snapshot(processor, &format_day(day))?;
+                }
+            }
+            SnapshotInterval::Rows(_) => {}
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_line_snapshot(&mut self, processor: &Processor) -> Result<()> {
+        self.write_snapshot(processor, &format!("line:{}", self.last_line))?;
+        self.pending_rows = 0;
+        Ok(())
+    }
+
+    fn write_snapshot(&mut self, processor: &Processor, as_of: &str) -> Result<()> {
+        let mut records: Vec<ClientRecord> = processor.client_records().collect();
+        records.sort_by_key(|record| (record.client.0, record.currency));
+        for record in records {
+            self.writer.serialize(SnapshotRecord {
+                as_of,
+                client: record.client,
+                currency: record.currency,
+                available: record.available,
+                held: record.held,
+                total: record.total,
+                locked: record.locked,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a number of days since the Unix epoch as a `YYYY-MM-DD` date.
+fn format_day(days: u64) -> String {
+    // Civil calendar conversion over 400-year eras, shifted so that years start in March.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Business rules the engine applies that can be changed from their defaults, e.g. with a
+/// TOML policy file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Only deposits open new accounts. Otherwise any row opens an empty account for an
+    /// unknown client, even if the row itself is rejected.
+    pub only_deposits_create_accounts: bool,
+    /// Reject disputes, resolves and chargebacks on locked accounts, not only deposits and
+    /// withdrawals.
+    pub locked_rejects_disputes: bool,
+    /// What to do when a disputed deposit was already (partly) withdrawn.
+    pub dispute_shortfall: DisputeShortfall,
+    /// Allow disputing a deposit again after its dispute was resolved.
+    pub redispute_resolved: bool,
+}
+
+/// How a dispute is handled when the client's available balance is less than the disputed
+/// amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisputeShortfall {
+    /// Hold the whole amount, leaving available negative.
+    #[default]
+    Overdraw,
+    /// Reject the dispute.
+    Reject,
+    /// Hold what is available and record the rest as debt of the client, which later deposits
+    /// pay off first.
+    Debt,
+}
+
+impl Default for Policy {
+    /// The rules described in the README assumptions.
+    fn default() -> Self {
+        Policy {
+            only_deposits_create_accounts: true,
+            locked_rejects_disputes: true,
+            dispute_shortfall: DisputeShortfall::Overdraw,
+            redispute_resolved: true,
+        }
+    }
+}
+
+impl Policy {
+    /// Reads a TOML policy file. Rules missing from the file keep their default.
+    pub fn load(filename: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(filename)
+            .with_context(|| format!("failed to read policy: {filename}"))?;
+        toml::from_str(&content).with_context(|| format!("invalid policy: {filename}"))
+    }
+}
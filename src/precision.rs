@@ -0,0 +1,85 @@
+use anyhow::{Result, bail};
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::amount::Amount;
+
+/// Number of decimal places amounts are accepted with and printed with by default.
+pub const DEFAULT_MAX_SCALE: u32 = 4;
+
+/// How amounts with more decimal places than allowed are handled.
+#[derive(Debug, Clone, Copy)]
+pub enum PrecisionMode {
+    Reject,
+    Round(RoundingStrategy),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Precision {
+    max_scale: u32,
+    mode: PrecisionMode,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision {
+            max_scale: DEFAULT_MAX_SCALE,
+            mode: PrecisionMode::Reject,
+        }
+    }
+}
+
+impl Precision {
+    pub fn new(max_scale: u32, mode: PrecisionMode) -> Result<Self> {
+        if max_scale > Amount::SCALE {
+            bail!("max scale cannot exceed {}", Amount::SCALE);
+        }
+        Ok(Precision { max_scale, mode })
+    }
+
+    #[must_use]
+    pub fn max_scale(self) -> u32 {
+        self.max_scale
+    }
+
+    #[must_use]
+    pub fn exceeds(self, amount: Decimal) -> bool {
+        amount.normalize().scale() > self.max_scale
+    }
+
+    /// Rejects or rounds an amount with too many decimal places, depending on the mode.
+    pub fn apply(self, amount: Decimal) -> Result<Decimal> {
+        if !self.exceeds(amount) {
+            return Ok(amount);
+        }
+        match self.mode {
+            PrecisionMode::Reject => bail!(
+                "amount {amount} has more than {} decimal places",
+                self.max_scale
+            ),
+            PrecisionMode::Round(strategy) => {
+                Ok(amount.round_dp_with_strategy(self.max_scale, strategy))
+            }
+        }
+    }
+
+    /// Returns the amount with exactly `max_scale` decimal places for output.
+    #[must_use]
+    pub fn normalize(self, amount: Amount) -> Decimal {
+        let mut amount = amount.to_decimal();
+        amount.rescale(self.max_scale);
+        amount
+    }
+}
+
+pub fn parse_rounding_strategy(name: &str) -> Result<RoundingStrategy> {
+    Ok(match name {
+        "half-even" => RoundingStrategy::MidpointNearestEven,
+        "half-up" => RoundingStrategy::MidpointAwayFromZero,
+        "half-down" => RoundingStrategy::MidpointTowardZero,
+        "down" => RoundingStrategy::ToZero,
+        "up" => RoundingStrategy::AwayFromZero,
+        "floor" => RoundingStrategy::ToNegativeInfinity,
+        "ceiling" => RoundingStrategy::ToPositiveInfinity,
+        _ => bail!("unknown rounding strategy: {name}"),
+    })
+}
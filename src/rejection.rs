@@ -0,0 +1,114 @@
+use std::fmt;
+
+use crate::amount::Amount;
+
+use crate::transaction::{ClientId, TransactionId};
+
+/// Reason a transaction was not applied to the ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    UnknownAccount {
+        operation: &'static str,
+        client: ClientId,
+    },
+    LockedAccount {
+        operation: &'static str,
+        client: ClientId,
+    },
+    DuplicateTransaction(TransactionId),
+    UnknownTransaction {
+        operation: &'static str,
+        tx: TransactionId,
+    },
+    AlreadyDisputed(TransactionId),
+    /// The deposit was disputed and resolved before and the policy forbids disputing it again.
+    AlreadyResolved(TransactionId),
+    NotDisputed {
+        operation: &'static str,
+        tx: TransactionId,
+    },
+    InsufficientFunds {
+        client: ClientId,
+        available: Amount,
+        requested: Amount,
+    },
+    AmountLimit {
+        amount: Amount,
+        limit: Amount,
+    },
+    BalanceLimit {
+        client: ClientId,
+        balance: Amount,
+        limit: Amount,
+    },
+    Overflow {
+        operation: &'static str,
+        client: ClientId,
+    },
+    /// The client is on the blocklist or missing from the allowlist.
+    Blocked {
+        client: ClientId,
+    },
+    /// The client already reached one of the velocity limits, described by `limit`.
+    VelocityLimit {
+        client: ClientId,
+        limit: String,
+    },
+    /// The row couldn't be turned into a transaction, e.g. because of a missing amount.
+    InvalidRecord(String),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::UnknownAccount { operation, client } => {
+                write!(f, "{operation} for non existing account: {client:?}")
+            }
+            Rejection::LockedAccount { operation, client } => {
+                write!(f, "{operation} for locked account: {client:?}")
+            }
+            Rejection::DuplicateTransaction(tx) => write!(f, "duplicate transaction: {tx:?}"),
+            Rejection::UnknownTransaction { operation, tx } => {
+                write!(f, "{operation} for non existing transaction: {tx:?}")
+            }
+            Rejection::AlreadyDisputed(tx) => {
+                write!(f, "transaction already under dispute: {tx:?}")
+            }
+            Rejection::AlreadyResolved(tx) => write!(f, "transaction already resolved: {tx:?}"),
+            Rejection::NotDisputed { operation, tx } => {
+                write!(f, "{operation} for transaction not under dispute: {tx:?}")
+            }
+            Rejection::InsufficientFunds {
+                client,
+                available,
+                requested,
+            } => write!(
+                f,
+                "insufficient funds (available: {available}, requested: {requested}): {client:?}"
+            ),
+            Rejection::AmountLimit { amount, limit } => {
+                write!(f, "amount {amount} exceeds transaction limit {limit}")
+            }
+            Rejection::BalanceLimit {
+                client,
+                balance,
+                limit,
+            } => write!(
+                f,
+                "balance {balance} would exceed balance limit {limit}: {client:?}"
+            ),
+            Rejection::Overflow { operation, client } => {
+                write!(f, "{operation} would overflow balances: {client:?}")
+            }
+            Rejection::Blocked { client } => {
+                write!(f, "client not allowed to transact: {client:?}")
+            }
+            Rejection::VelocityLimit { client, limit } => {
+                write!(f, "velocity limit reached ({limit}): {client:?}")
+            }
+            Rejection::InvalidRecord(reason) => write!(f, "invalid record: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
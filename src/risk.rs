@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::engine::Step;
+use crate::transaction::{ClientId, TransactionType};
+
+/// Number of decimal places ratios are reported with.
+const RATIO_SCALE: u32 = 4;
+
+/// Ratios above which a client is flagged in the risk report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RiskThresholds {
+    /// Maximum disputes per deposit.
+    pub dispute_ratio: Decimal,
+    /// Maximum chargebacks per deposit.
+    pub chargeback_ratio: Decimal,
+}
+
+impl Default for RiskThresholds {
+    /// 1% for both ratios.
+    fn default() -> Self {
+        RiskThresholds {
+            dispute_ratio: Decimal::new(1, 2),
+            chargeback_ratio: Decimal::new(1, 2),
+        }
+    }
+}
+
+/// Per-client counts and amounts of applied deposits, disputes, resolves and chargebacks.
+#[derive(Debug, Default)]
+pub struct RiskReport {
+    clients: BTreeMap<u16, ClientActivity>,
+}
+
+/// Applied transactions of a kind: how many and their total amount.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Activity {
+    pub count: u64,
+    pub amount: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientActivity {
+    pub deposits: Activity,
+    pub disputes: Activity,
+    pub resolves: Activity,
+    pub chargebacks: Activity,
+}
+
+impl ClientActivity {
+    /// Disputes per deposit, or `None` without deposits.
+    #[must_use]
+    pub fn dispute_ratio(&self) -> Option<Decimal> {
+        self.ratio(self.disputes)
+    }
+
+    /// Chargebacks per deposit, or `None` without deposits.
+    #[must_use]
+    pub fn chargeback_ratio(&self) -> Option<Decimal> {
+        self.ratio(self.chargebacks)
+    }
+
+    /// Whether either ratio is above its threshold.
+    #[must_use]
+    pub fn exceeds(&self, thresholds: RiskThresholds) -> bool {
+        self.dispute_ratio()
+            .is_some_and(|ratio| ratio > thresholds.dispute_ratio)
+            || self
+                .chargeback_ratio()
+                .is_some_and(|ratio| ratio > thresholds.chargeback_ratio)
+    }
+
+    fn ratio(&self, activity: Activity) -> Option<Decimal> {
+        (self.deposits.count > 0)
+            .then(|| Decimal::from(activity.count) / Decimal::from(self.deposits.count))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RiskRecord {
+    client: ClientId,
+    deposits: u64,
+    deposit_amount: Decimal,
+    disputes: u64,
+    dispute_amount: Decimal,
+    resolves: u64,
+    resolve_amount: Decimal,
+    chargebacks: u64,
+    chargeback_amount: Decimal,
+    dispute_ratio: Option<Decimal>,
+    chargeback_ratio: Option<Decimal>,
+    flagged: bool,
+}
+
+impl RiskReport {
+    #[must_use]
+    pub fn new() -> Self {
+        RiskReport::default()
+    }
+
+    /// Counts an applied transaction. Rejected ones are ignored.
+    pub fn record(&mut self, step: &Step) {
+        let (Ok(()), Some(amount)) = (&step.result, step.amount) else {
+            return;
+        };
+        let client = self.clients.entry(step.client.0).or_default();
+        let activity = match step.tx_type {
+            TransactionType::Deposit => &mut client.deposits,
+            TransactionType::Dispute => &mut client.disputes,
+            TransactionType::Resolve => &mut client.resolves,
+            TransactionType::Chargeback => &mut client.chargebacks,
+            TransactionType::Withdrawal | TransactionType::Transfer => return,
+        };
+        activity.count += 1;
+        activity.amount += amount.to_decimal();
+    }
+
+    /// Activity of a client, if it had any applied deposit, dispute, resolve or chargeback.
+    #[must_use]
+    pub fn client(&self, client: ClientId) -> Option<&ClientActivity> {
+        self.clients.get(&client.0)
+    }
+
+    /// Writes one row per client, ordered by client, flagging those above `thresholds`.
+    pub fn write_csv<W: Write>(&self, thresholds: RiskThresholds, writer: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for (&client, activity) in &self.clients {
+            let amount = |activity: Activity| with_scale(activity.amount, Amount::SCALE);
+            let ratio = |ratio: Decimal| with_scale(ratio, RATIO_SCALE);
+            writer.serialize(RiskRecord {
+                client: ClientId(client),
+                deposits: activity.deposits.count,
+                deposit_amount: amount(activity.deposits),
+                disputes: activity.disputes.count,
+                dispute_amount: amount(activity.disputes),
+                resolves: activity.resolves.count,
+                resolve_amount: amount(activity.resolves),
+                chargebacks: activity.chargebacks.count,
+                chargeback_amount: amount(activity.chargebacks),
+                dispute_ratio: activity.dispute_ratio().map(ratio),
+                chargeback_ratio: activity.chargeback_ratio().map(ratio),
+                flagged: activity.exceeds(thresholds),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn with_scale(mut value: Decimal, scale: u32) -> Decimal {
+    value.rescale(scale);
+    value
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::amount::Amount;
+use crate::precision::Precision;
+
+#[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize)]
+pub struct ClientId(pub u16);
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Serialize)]
+pub struct TransactionId(pub u32);
+
+/// A three-letter currency code such as `EUR`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Currency([u8; 3]);
+
+impl FromStr for Currency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match <[u8; 3]>::try_from(s.as_bytes()) {
+            Ok(code) if code.iter().all(u8::is_ascii_uppercase) => Ok(Currency(code)),
+            _ => bail!("invalid currency: {s}"),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Always valid UTF-8 as it's made of ASCII letters.
+        f.write_str(std::str::from_utf8(&self.0).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Transaction {
+    Deposit {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Amount,
+    },
+    Withdrawal {
+        client: ClientId,
+        amount: Amount,
+    },
+    Dispute {
+        client: ClientId,
+        tx: TransactionId,
+    },
+    Resolve {
+        client: ClientId,
+        tx: TransactionId,
+    },
+    Chargeback {
+        client: ClientId,
+        tx: TransactionId,
+    },
+    /// Moves `amount` from the available balance of `client` to that of `destination`.
+    Transfer {
+        client: ClientId,
+        destination: ClientId,
+        amount: Amount,
+    },
+}
+
+impl Transaction {
+    /// Validates a parsed input row and turns it into a transaction.
+    pub fn from_record(record: &TransactionRecord, precision: Precision) -> Result<Self> {
+        let client = record.client;
+        let tx = record.tx;
+        match record.tx_type {
+            TransactionType::Deposit => {
+                let amount = record.parsed_amount(precision)?;
+                Ok(Transaction::Deposit { client, tx, amount })
+            }
+            TransactionType::Withdrawal => {
+                let amount = record.parsed_amount(precision)?;
+                Ok(Transaction::Withdrawal { client, amount })
+            }
+            TransactionType::Dispute => Ok(Transaction::Dispute { client, tx }),
+            TransactionType::Resolve => Ok(Transaction::Resolve { client, tx }),
+            TransactionType::Chargeback => Ok(Transaction::Chargeback { client, tx }),
+            TransactionType::Transfer => {
+                let destination = record
+                    .destination
+                    .ok_or_else(|| anyhow!("missing destination"))?;
+                if destination == client {
+                    bail!("transfer to the same client");
+                }
+                let amount = record.parsed_amount(precision)?;
+                Ok(Transaction::Transfer {
+                    client,
+                    destination,
+                    amount,
+                })
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn client(&self) -> ClientId {
+        match self {
+            Transaction::Deposit { client, .. }
+            | Transaction::Withdrawal { client, .. }
+            | Transaction::Dispute { client, .. }
+            | Transaction::Resolve { client, .. }
+            | Transaction::Chargeback { client, .. }
+            | Transaction::Transfer { client, .. } => *client,
+        }
+    }
+
+    #[must_use]
+    pub fn tx_type(&self) -> TransactionType {
+        match self {
+            Transaction::Deposit { .. } => TransactionType::Deposit,
+            Transaction::Withdrawal { .. } => TransactionType::Withdrawal,
+            Transaction::Dispute { .. } => TransactionType::Dispute,
+            Transaction::Resolve { .. } => TransactionType::Resolve,
+            Transaction::Chargeback { .. } => TransactionType::Chargeback,
+            Transaction::Transfer { .. } => TransactionType::Transfer,
+        }
+    }
+
+    /// The amount moved by a deposit, withdrawal or transfer.
+    #[must_use]
+    pub fn amount(&self) -> Option<Amount> {
+        match self {
+            Transaction::Deposit { amount, .. }
+            | Transaction::Withdrawal { amount, .. }
+            | Transaction::Transfer { amount, .. } => Some(*amount),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionRecord {
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    #[serde(default, with = "rust_decimal::serde::str_option")]
+    pub amount: Option<Decimal>,
+    /// Optional time of the transaction, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Currency of a deposit or withdrawal, only in inputs with a currency column. Disputes,
+    /// resolves and chargebacks can leave it out as they're bound to the deposit's currency.
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    /// Client credited by a transfer, only in inputs with a destination column.
+    #[serde(default)]
+    pub destination: Option<ClientId>,
+}
+
+impl TransactionRecord {
+    pub fn validated_amount(&self) -> Result<Decimal> {
+        let amount = self.amount.ok_or_else(|| anyhow!("missing amount"))?;
+        if amount < Decimal::ZERO {
+            bail!("negative amount not allowed");
+        }
+        Ok(amount)
+    }
+
+    /// Validates the amount and converts it to an [`Amount`] after applying `precision`.
+    pub fn parsed_amount(&self, precision: Precision) -> Result<Amount> {
+        let amount = precision.apply(self.validated_amount()?)?;
+        Amount::try_from(amount)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Transfer,
+}
+
+impl TransactionType {
+    #[must_use]
+    pub fn label(self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Transfer => "transfer",
+        }
+    }
+}
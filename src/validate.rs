@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap, hash_map::Entry};
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::amount::Amount;
+use crate::precision::Precision;
+use crate::transaction::{ClientId, TransactionId, TransactionRecord, TransactionType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Problem {
+    Read,
+    Amount,
+    Precision,
+    DuplicateTx,
+    UnknownReference,
+    Destination,
+}
+
+impl Problem {
+    fn label(self) -> &'static str {
+        match self {
+            Problem::Read => "read",
+            Problem::Amount => "amount",
+            Problem::Precision => "precision",
+            Problem::DuplicateTx => "duplicate-tx",
+            Problem::UnknownReference => "unknown-reference",
+            Problem::Destination => "destination",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProblemClass {
+    count: usize,
+    examples: Vec<(u64, String)>,
+}
+
+#[derive(Debug)]
+pub struct ValidationReport {
+    rows: usize,
+    max_examples: usize,
+    problems: BTreeMap<Problem, ProblemClass>,
+}
+
+impl ValidationReport {
+    fn new(max_examples: usize) -> Self {
+        ValidationReport {
+            rows: 0,
+            max_examples,
+            problems: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, problem: Problem, line: u64, message: String) {
+        let class = self.problems.entry(problem).or_default();
+        class.count += 1;
+        if class.examples.len() < self.max_examples {
+            class.examples.push((line, message));
+        }
+    }
+
+    #[must_use]
+    pub fn problem_count(&self) -> usize {
+        self.problems.values().map(|class| class.count).sum()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rows checked: {}", self.rows)?;
+        writeln!(f, "problems found: {}", self.problem_count())?;
+        for (problem, class) in &self.problems {
+            writeln!(f, "{} ({}):", problem.label(), class.count)?;
+            for (line, message) in &class.examples {
+                writeln!(f, "  line {line}: {message}")?;
+            }
+            let hidden = class.count - class.examples.len();
+            if hidden > 0 {
+                writeln!(f, "  ... and {hidden} more")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SeenTransaction {
+    client: ClientId,
+    tx_type: TransactionType,
+    line: u64,
+}
+
+/// Runs all input checks without applying any transaction to a ledger.
+pub fn validate<R: std::io::Read>(
+    mut reader: csv::Reader<R>,
+    precision: Precision,
+    max_examples: usize,
+) -> Result<ValidationReport> {
+    let mut report = ValidationReport::new(max_examples);
+    let mut seen: HashMap<TransactionId, SeenTransaction> = HashMap::new();
+    let headers = reader.headers()?.clone();
+    let mut raw = csv::StringRecord::new();
+
+    loop {
+        match reader.read_record(&mut raw) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => {
+                report.rows += 1;
+                let line = e.position().map_or(0, csv::Position::line);
+                report.record(Problem::Read, line, e.to_string());
+                continue;
+            }
+        }
+        report.rows += 1;
+        let line = raw.position().map_or(0, csv::Position::line);
+        let record: TransactionRecord = match raw.deserialize(Some(&headers)) {
+            Ok(record) => record,
+            Err(e) => {
+                report.record(Problem::Read, line, e.to_string());
+                continue;
+            }
+        };
+        check_record(&mut report, &mut seen, &record, precision, line);
+    }
+
+    Ok(report)
+}
+
+fn check_record(
+    report: &mut ValidationReport,
+    seen: &mut HashMap<TransactionId, SeenTransaction>,
+    record: &TransactionRecord,
+    precision: Precision,
+    line: u64,
+) {
+    let tx = record.tx;
+    match record.tx_type {
+        TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => {
+            if record.tx_type == TransactionType::Transfer {
+                match record.destination {
+                    None => report.record(
+                        Problem::Destination,
+                        line,
+                        "transfer without destination".to_string(),
+                    ),
+                    Some(destination) if destination == record.client => report.record(
+                        Problem::Destination,
+                        line,
+                        format!("transfer to the same client: {destination:?}"),
+                    ),
+                    Some(_) => {}
+                }
+            }
+            let amount = match record.validated_amount() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    report.record(Problem::Amount, line, e.to_string());
+                    return;
+                }
+            };
+            match precision.apply(amount) {
+                Ok(amount) => {
+                    if let Err(e) = Amount::try_from(amount) {
+                        report.record(Problem::Amount, line, e.to_string());
+                    }
+                }
+                Err(e) => report.record(Problem::Precision, line, e.to_string()),
+            }
+            match seen.entry(tx) {
+                Entry::Occupied(entry) => report.record(
+                    Problem::DuplicateTx,
+                    line,
+                    format!("{tx:?} already used on line {}", entry.get().line),
+                ),
+                Entry::Vacant(entry) => {
+                    entry.insert(SeenTransaction {
+                        client: record.client,
+                        tx_type: record.tx_type,
+                        line,
+                    });
+                }
+            }
+        }
+        TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+            let operation = record.tx_type.label();
+            let message = match seen.get(&tx) {
+                None => format!("{operation} for unknown transaction: {tx:?}"),
+                Some(target) if target.client != record.client => format!(
+                    "{operation} by {:?} for transaction of {:?}: {tx:?}",
+                    record.client, target.client
+                ),
+                Some(target) if !matches!(target.tx_type, TransactionType::Deposit) => {
+                    format!("{operation} for non deposit transaction: {tx:?}")
+                }
+                Some(_) => return,
+            };
+            report.record(Problem::UnknownReference, line, message);
+        }
+    }
+}
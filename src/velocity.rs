@@ -0,0 +1,226 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::amount::Amount;
+use crate::rejection::Rejection;
+use crate::transaction::{ClientId, Transaction, TransactionType};
+
+/// Rolling-window limits on the number and amount of each client's deposits or withdrawals,
+/// read from a TOML file.
+#[derive(Debug, Clone)]
+pub struct VelocityLimits {
+    limits: Vec<VelocityLimit>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VelocityLimitsFile {
+    #[serde(rename = "limit")]
+    limits: Vec<VelocityLimitRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VelocityLimitRecord {
+    operation: TransactionType,
+    window_rows: Option<u64>,
+    window_seconds: Option<u64>,
+    max_count: Option<usize>,
+    max_amount: Option<Decimal>,
+}
+
+/// A limit on the deposits or withdrawals of a client within a window.
+#[derive(Debug, Clone, Copy)]
+struct VelocityLimit {
+    operation: TransactionType,
+    window: Window,
+    max_count: Option<usize>,
+    max_amount: Option<Amount>,
+}
+
+/// How far back a limit looks, including the current row.
+#[derive(Debug, Clone, Copy)]
+enum Window {
+    /// The last `n` rows of the input.
+    Rows(u64),
+    /// The last `n` seconds, by the rows' timestamps. Rows without one aren't limited.
+    Seconds(u64),
+}
+
+/// A client's applied transactions within the window of one limit, by line or timestamp.
+#[derive(Debug, Clone, Default)]
+pub struct VelocityWindow {
+    entries: VecDeque<(u64, Amount)>,
+    /// Sum of the amounts of `entries`, in `i128` so that it can't overflow.
+    total: i128,
+}
+
+impl fmt::Display for VelocityLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operation = self.operation.label();
+        match (self.max_count, self.max_amount) {
+            (Some(count), Some(amount)) => {
+                write!(f, "max {count} {operation}s or {amount} in total")?;
+            }
+            (Some(count), None) => write!(f, "max {count} {operation}s")?,
+            (None, Some(amount)) => write!(f, "max {amount} in {operation}s")?,
+            (None, None) => write!(f, "no {operation} limit")?,
+        }
+        match self.window {
+            Window::Rows(rows) => write!(f, " per {rows} rows"),
+            Window::Seconds(seconds) => write!(f, " per {seconds} seconds"),
+        }
+    }
+}
+
+impl Window {
+    /// The position of the current row within this kind of window, if it has one.
+    fn position(self, line: u64, timestamp: Option<u64>) -> Option<u64> {
+        match self {
+            Window::Rows(_) => Some(line),
+            Window::Seconds(_) => timestamp,
+        }
+    }
+
+    /// Whether an entry at `position` is within the window at `now`. Entries after `now`, from
+    /// rows with out-of-order timestamps, are.
+    fn contains(self, now: u64, position: u64) -> bool {
+        let size = match self {
+            Window::Rows(size) | Window::Seconds(size) => size,
+        };
+        now.saturating_sub(position) < size
+    }
+}
+
+impl VelocityLimits {
+    /// Reads and checks a TOML file of velocity limits.
+    pub fn load(filename: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(filename)
+            .with_context(|| format!("failed to read velocity limits: {filename}"))?;
+        let file: VelocityLimitsFile = toml::from_str(&content)
+            .with_context(|| format!("invalid velocity limits: {filename}"))?;
+        let limits = file
+            .limits
+            .into_iter()
+            .enumerate()
+            .map(|(index, record)| {
+                VelocityLimit::from_record(&record)
+                    .with_context(|| format!("invalid velocity limit {}: {filename}", index + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(VelocityLimits { limits })
+    }
+
+    /// Checks that applying `transaction` on `line` keeps the client within every limit, given
+    /// its `windows` (one per limit, created as needed).
+    pub fn check(
+        &self,
+        windows: &mut Vec<VelocityWindow>,
+        line: u64,
+        timestamp: Option<u64>,
+        transaction: Transaction,
+    ) -> Result<(), Rejection> {
+        windows.resize_with(self.limits.len(), VelocityWindow::default);
+        for (limit, window) in self.limits.iter().zip(windows) {
+            let Some((client, amount)) = limit.applies_to(transaction) else {
+                continue;
+            };
+            let Some(now) = limit.window.position(line, timestamp) else {
+                continue;
+            };
+            window.expire(limit.window, now);
+            let exceeded = limit
+                .max_count
+                .is_some_and(|max_count| window.entries.len() >= max_count)
+                || limit.max_amount.is_some_and(|max_amount| {
+                    window.total + i128::from(amount.units()) > i128::from(max_amount.units())
+                });
+            if exceeded {
+                return Err(Rejection::VelocityLimit {
+                    client,
+                    limit: limit.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds an applied `transaction` to the client's `windows`.
+    pub fn record(
+        &self,
+        windows: &mut Vec<VelocityWindow>,
+        line: u64,
+        timestamp: Option<u64>,
+        transaction: Transaction,
+    ) {
+        windows.resize_with(self.limits.len(), VelocityWindow::default);
+        for (limit, window) in self.limits.iter().zip(windows) {
+            if let (Some((_, amount)), Some(now)) = (
+                limit.applies_to(transaction),
+                limit.window.position(line, timestamp),
+            ) {
+                window.entries.push_back((now, amount));
+                window.total += i128::from(amount.units());
+            }
+        }
+    }
+}
+
+impl VelocityLimit {
+    fn from_record(record: &VelocityLimitRecord) -> Result<Self> {
+        if !matches!(
+            record.operation,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) {
+            bail!("only deposits and withdrawals can be limited");
+        }
+        let window = match (record.window_rows, record.window_seconds) {
+            (Some(rows), None) => Window::Rows(rows),
+            (None, Some(seconds)) => Window::Seconds(seconds),
+            _ => bail!("needs exactly one of window_rows and window_seconds"),
+        };
+        if record.max_count.is_none() && record.max_amount.is_none() {
+            bail!("needs max_count and/or max_amount");
+        }
+        Ok(VelocityLimit {
+            operation: record.operation,
+            window,
+            max_count: record.max_count,
+            max_amount: record.max_amount.map(Amount::try_from).transpose()?,
+        })
+    }
+
+    /// The client and amount of `transaction` if it's of the limited kind. Transfers count
+    /// as withdrawals of the source.
+    fn applies_to(&self, transaction: Transaction) -> Option<(ClientId, Amount)> {
+        match (self.operation, transaction) {
+            (TransactionType::Deposit, Transaction::Deposit { client, amount, .. })
+            | (
+                TransactionType::Withdrawal,
+                Transaction::Withdrawal { client, amount }
+                | Transaction::Transfer { client, amount, .. },
+            ) => Some((client, amount)),
+            _ => None,
+        }
+    }
+}
+
+impl VelocityWindow {
+    /// Drops the entries that are no longer within `window` at position `now`. Timestamps
+    /// aren't necessarily in order, so the oldest entries can be anywhere in the window.
+    fn expire(&mut self, window: Window, now: u64) {
+        let mut expired = 0;
+        self.entries.retain(|&(position, amount)| {
+            let within = window.contains(now, position);
+            if !within {
+                expired += i128::from(amount.units());
+            }
+            within
+        });
+        self.total -= expired;
+    }
+}
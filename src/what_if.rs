@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::diff::AccountsDiff;
+use crate::engine::Step;
+use crate::rejection::Rejection;
+use crate::transaction::{ClientId, TransactionId, TransactionType};
+
+/// The rejected rows of a run, by line.
+#[derive(Debug, Default)]
+pub struct Rejections {
+    by_line: BTreeMap<u64, RejectedRow>,
+}
+
+#[derive(Debug, Clone)]
+struct RejectedRow {
+    client: ClientId,
+    tx: TransactionId,
+    tx_type: TransactionType,
+    rejection: Rejection,
+}
+
+impl Rejections {
+    #[must_use]
+    pub fn new() -> Self {
+        Rejections::default()
+    }
+
+    pub fn record(&mut self, step: &Step) {
+        if let Err(rejection) = &step.result {
+            let row = RejectedRow {
+                client: step.client,
+                tx: step.tx,
+                tx_type: step.tx_type,
+                rejection: rejection.clone(),
+            };
+            self.by_line.insert(step.line, row);
+        }
+    }
+}
+
+/// A row whose outcome differs between the two runs. `None` means it was applied.
+#[derive(Debug, Clone)]
+pub struct OutcomeDifference {
+    pub line: u64,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub tx_type: TransactionType,
+    pub baseline: Option<Rejection>,
+    pub alternative: Option<Rejection>,
+}
+
+/// Rows rejected in only one of the runs, or for different kinds of reasons, in input order.
+#[must_use]
+pub fn compare_outcomes(baseline: &Rejections, alternative: &Rejections) -> Vec<OutcomeDifference> {
+    let mut lines: Vec<u64> = baseline
+        .by_line
+        .keys()
+        .chain(alternative.by_line.keys())
+        .copied()
+        .collect();
+    lines.sort_unstable();
+    lines.dedup();
+
+    lines
+        .into_iter()
+        .filter_map(|line| {
+            let baseline = baseline.by_line.get(&line);
+            let alternative = alternative.by_line.get(&line);
+            let row = baseline.or(alternative)?;
+            let rejection = |row: Option<&RejectedRow>| row.map(|row| row.rejection.clone());
+            // Rejections for the same reason can still differ in their amounts, which only
+            // reflect the balances that already differ.
+            let reason =
+                |row: Option<&RejectedRow>| row.map(|row| std::mem::discriminant(&row.rejection));
+            (reason(baseline) != reason(alternative)).then(|| OutcomeDifference {
+                line,
+                client: row.client,
+                tx: row.tx,
+                tx_type: row.tx_type,
+                baseline: rejection(baseline),
+                alternative: rejection(alternative),
+            })
+        })
+        .collect()
+}
+
+/// How the results of a run change under an alternative policy.
+#[derive(Debug)]
+pub struct WhatIfReport {
+    pub balances: AccountsDiff,
+    pub outcomes: Vec<OutcomeDifference>,
+}
+
+impl fmt::Display for WhatIfReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Balances (baseline -> alternative):")?;
+        write!(f, "{}", self.balances)?;
+        writeln!(f, "Transactions:")?;
+        for outcome in &self.outcomes {
+            write!(
+                f,
+                "line {}: {} of tx {} for client {}: ",
+                outcome.line,
+                outcome.tx_type.label(),
+                outcome.tx.0,
+                outcome.client.0
+            )?;
+            match (&outcome.baseline, &outcome.alternative) {
+                (Some(baseline), Some(alternative)) => writeln!(
+                    f,
+                    "rejected by both, baseline: {baseline}, alternative: {alternative}"
+                )?,
+                (Some(baseline), None) => {
+                    writeln!(f, "rejected only by the baseline: {baseline}")?;
+                }
+                (None, Some(alternative)) => {
+                    writeln!(f, "rejected only by the alternative: {alternative}")?;
+                }
+                (None, None) => writeln!(f, "applied by both")?,
+            }
+        }
+        writeln!(
+            f,
+            "{} transactions with different outcomes.",
+            self.outcomes.len()
+        )
+    }
+}
@@ -18,8 +18,19 @@ const BIN_PATH: &str = env!("CARGO_BIN_EXE_yet-another-transactions-processor");
 
 /// Runs the payments engine with the given input CSV via STDIN and returns parsed output.
 fn run_engine(input: &str) -> Vec<ClientRecord> {
+    run_engine_with_args(&["-"], input)
+}
+
+/// Runs the payments engine with the given arguments and input CSV via STDIN and returns
+/// parsed output.
+fn run_engine_with_args(args: &[&str], input: &str) -> Vec<ClientRecord> {
+    parse_output(&run_ok(args, input))
+}
+
+/// Runs the binary with the given arguments and input via STDIN and returns the raw output.
+fn run_command(args: &[&str], input: &str) -> std::process::Output {
     let mut child = Command::new(BIN_PATH)
-        .arg("-")
+        .args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -33,7 +44,13 @@ fn run_engine(input: &str) -> Vec<ClientRecord> {
         .write_all(input.as_bytes())
         .expect("Failed to write to stdin");
 
-    let output = child.wait_with_output().expect("Failed to read stdout");
+    child.wait_with_output().expect("Failed to read stdout")
+}
+
+/// Runs the binary with the given arguments and input via STDIN, asserts that it succeeded and
+/// returns its STDOUT.
+fn run_ok(args: &[&str], input: &str) -> String {
+    let output = run_command(args, input);
 
     assert!(
         output.status.success(),
@@ -43,8 +60,15 @@ fn run_engine(input: &str) -> Vec<ClientRecord> {
         String::from_utf8_lossy(&output.stderr)
     );
 
-    let stdout = String::from_utf8(output.stdout).expect("Invalid UTF-8");
-    parse_output(&stdout)
+    String::from_utf8(output.stdout).expect("Invalid UTF-8")
+}
+
+/// Sorts the rows of a CSV output after its header, as clients are written in no particular
+/// order.
+fn sorted_rows(output: &str) -> String {
+    let mut lines: Vec<&str> = output.lines().collect();
+    lines[1..].sort_unstable();
+    lines.join("\n")
 }
 
 /// Runs the payments engine with input from a file and returns parsed output.
@@ -86,6 +110,15 @@ fn assert_records_eq(mut actual: Vec<ClientRecord>, mut expected: Vec<ClientReco
     assert_eq!(actual, expected);
 }
 
+/// Writes `content` to a temporary file, e.g. for options that take a file path.
+fn temp_file(content: &str) -> tempfile::NamedTempFile {
+    let mut file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+    file.write_all(content.as_bytes())
+        .expect("Failed to write temp file");
+    file.flush().expect("Failed to flush temp file");
+    file
+}
+
 /// Parses a string into a Decimal for test assertions.
 fn dec(s: &str) -> Decimal {
     s.parse().unwrap()
@@ -861,6 +894,89 @@ withdrawal,1,2,99.9999";
 
         assert_records_eq(actual, expected);
     }
+
+    /// Amounts with more than 4 decimal places are rejected by default.
+    #[test]
+    fn excess_precision_rejected_by_default() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.23456";
+
+        let actual = run_engine(input);
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("1.0"),
+            held: dec("0.0"),
+            total: dec("1.0"),
+            locked: false,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
+
+    /// Excess precision can be rounded with a chosen strategy instead of rejected.
+    #[test]
+    fn excess_precision_rounded() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.23455
+deposit,2,2,1.23459";
+
+        let half_even = run_engine_with_args(&["--excess-precision", "round", "-"], input);
+        let down = run_engine_with_args(
+            &["--excess-precision", "round", "--rounding", "down", "-"],
+            input,
+        );
+
+        assert_eq!(
+            half_even[0].available + half_even[1].available,
+            dec("2.4692")
+        );
+        assert_eq!(down[0].available + down[1].available, dec("2.4690"));
+    }
+
+    /// Output amounts always have exactly the configured number of decimal places.
+    #[test]
+    fn output_fixed_scale() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.5";
+
+        let output = run_command(&["-"], input);
+        let stdout = String::from_utf8(output.stdout).expect("Invalid UTF-8");
+        assert!(
+            stdout.contains("1,1.5000,0.0000,1.5000,false"),
+            "stdout: {stdout}"
+        );
+
+        let output = run_command(&["--max-scale", "2", "-"], input);
+        let stdout = String::from_utf8(output.stdout).expect("Invalid UTF-8");
+        assert!(
+            stdout.contains("1,1.50,0.00,1.50,false"),
+            "stdout: {stdout}"
+        );
+    }
+
+    /// A lower max scale rejects amounts that would otherwise be valid.
+    #[test]
+    fn custom_max_scale() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,0.005";
+
+        let actual = run_engine_with_args(&["--max-scale", "2", "-"], input);
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("1.0"),
+            held: dec("0.0"),
+            total: dec("1.0"),
+            locked: false,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
 }
 
 // =============================================================================
@@ -1197,6 +1313,2967 @@ deposit,1,4,20.0";
     }
 }
 
+// =============================================================================
+// 12. Validate Command Tests
+// =============================================================================
+
+mod validate {
+    use super::*;
+
+    /// A clean file passes validation and reports no problems.
+    #[test]
+    fn clean_input() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,100.0
+withdrawal,1,2,40.0
+dispute,1,1,
+resolve,1,1,";
+
+        let stdout = run_ok(&["validate", "-"], input);
+
+        assert!(stdout.contains("rows checked: 4"), "stdout: {stdout}");
+        assert!(stdout.contains("problems found: 0"), "stdout: {stdout}");
+    }
+
+    /// Each problem class is counted and reported with its line number.
+    #[test]
+    fn reports_problem_classes() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,100.0
+deposit,1,1,5.0
+deposit,1,2,1.23456
+withdrawal,1,3,-1.0
+dispute,1,99,
+dispute,2,1,
+bogus,1,4,1.0";
+
+        let output = run_command(&["validate", "-"], input);
+        let stdout = String::from_utf8(output.stdout).expect("Invalid UTF-8");
+
+        assert!(!output.status.success(), "stdout: {stdout}");
+        assert!(stdout.contains("problems found: 6"), "stdout: {stdout}");
+        assert!(stdout.contains("duplicate-tx (1):"), "stdout: {stdout}");
+        assert!(stdout.contains("line 3: "), "stdout: {stdout}");
+        assert!(stdout.contains("precision (1):"), "stdout: {stdout}");
+        assert!(stdout.contains("amount (1):"), "stdout: {stdout}");
+        assert!(
+            stdout.contains("unknown-reference (2):"),
+            "stdout: {stdout}"
+        );
+        assert!(stdout.contains("read (1):"), "stdout: {stdout}");
+    }
+
+    /// Only the requested number of examples is shown per problem class.
+    #[test]
+    fn limits_examples() {
+        let input = "\
+type,client,tx,amount
+dispute,1,1,
+dispute,1,2,
+dispute,1,3,";
+
+        let output = run_command(&["validate", "--examples", "1", "-"], input);
+        let stdout = String::from_utf8(output.stdout).expect("Invalid UTF-8");
+
+        assert!(
+            stdout.contains("unknown-reference (3):"),
+            "stdout: {stdout}"
+        );
+        assert!(stdout.contains("line 2: "), "stdout: {stdout}");
+        assert!(!stdout.contains("line 3: "), "stdout: {stdout}");
+        assert!(stdout.contains("... and 2 more"), "stdout: {stdout}");
+    }
+}
+
+// =============================================================================
+// 13. Overflow and Limit Tests
+// =============================================================================
+
+mod limits {
+    use super::*;
+
+    /// A deposit that would overflow the balance is rejected instead of panicking.
+    #[test]
+    fn deposit_overflow_rejected() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,500000000000000.0
+deposit,1,2,500000000000000.0
+deposit,1,3,1.0";
+
+        let actual = run_engine(input);
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("500000000000001.0"),
+            held: dec("0.0"),
+            total: dec("500000000000001.0"),
+            locked: false,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
+
+    /// A dispute that would overflow the available balance is rejected and leaves balances as is.
+    #[test]
+    fn dispute_overflow_rejected() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,500000000000000.0
+withdrawal,1,2,500000000000000.0
+deposit,1,3,500000000000000.0
+dispute,1,1,
+dispute,1,3,";
+
+        let actual = run_engine(input);
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("0.0"),
+            held: dec("500000000000000.0"),
+            total: dec("500000000000000.0"),
+            locked: false,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
+
+    /// Balances stop short of the lowest integer, so that the overdraft used by the most
+    /// overdrawn client can still be reported.
+    #[test]
+    fn balance_range_is_symmetric() {
+        let accounts = temp_file(
+            "client,available,held,total,locked\n\
+             1,-922337203685477.5807,0,-922337203685477.5807,false\n",
+        );
+        let deposits = temp_file("client,tx,amount\n1,1,0.0001\n");
+        let limits = temp_file("client,allowance\n2,10\n");
+        let output = run_ok(
+            &[
+                "--opening-balances",
+                accounts.path().to_str().unwrap(),
+                "--open-deposits",
+                deposits.path().to_str().unwrap(),
+                "--overdraft-limits",
+                limits.path().to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount\ndispute,1,1,",
+        );
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,overdraft_used\n\
+             1,-922337203685477.5807,0.0000,-922337203685477.5807,false,922337203685477.5807\n"
+        );
+    }
+
+    /// Amounts too large to be represented are rejected.
+    #[test]
+    fn amount_out_of_range() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,79228162514264337593543950335";
+
+        let actual = run_engine(input);
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("1.0"),
+            held: dec("0.0"),
+            total: dec("1.0"),
+            locked: false,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
+
+    /// Deposits and withdrawals above the per-transaction limit are rejected.
+    #[test]
+    fn max_amount() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,100.0
+deposit,1,2,1000.0
+withdrawal,1,3,500.0";
+
+        let actual = run_engine_with_args(&["--max-amount", "200", "-"], input);
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("100.0"),
+            held: dec("0.0"),
+            total: dec("100.0"),
+            locked: false,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
+
+    /// Deposits that would push the total above the balance limit are rejected,
+    /// including the first deposit of a new client.
+    #[test]
+    fn max_balance() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,600.0
+deposit,1,2,500.0
+deposit,1,3,400.0
+deposit,2,4,1500.0";
+
+        let actual = run_engine_with_args(&["--max-balance", "1000", "-"], input);
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("1000.0"),
+            held: dec("0.0"),
+            total: dec("1000.0"),
+            locked: false,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
+}
+
+// =============================================================================
+// 14. Opening Balance Tests
+// =============================================================================
+
+mod opening_balances {
+    use super::*;
+
+    /// Opening balances are carried over and new transactions apply on top of them.
+    #[test]
+    fn carried_over() {
+        let accounts = temp_file(
+            "\
+client,available,held,total,locked
+1,100.0000,0.0000,100.0000,false
+2,5.0000,0.0000,5.0000,true",
+        );
+        let input = "\
+type,client,tx,amount
+withdrawal,1,1,30.0
+deposit,2,2,10.0
+deposit,3,3,1.0";
+
+        let path = accounts.path().to_str().unwrap();
+        let actual = run_engine_with_args(&["--opening-balances", path, "-"], input);
+        let expected = vec![
+            ClientRecord {
+                client: 1,
+                available: dec("70.0"),
+                held: dec("0.0"),
+                total: dec("70.0"),
+                locked: false,
+            },
+            ClientRecord {
+                client: 2,
+                available: dec("5.0"),
+                held: dec("0.0"),
+                total: dec("5.0"),
+                locked: true,
+            },
+            ClientRecord {
+                client: 3,
+                available: dec("1.0"),
+                held: dec("0.0"),
+                total: dec("1.0"),
+                locked: false,
+            },
+        ];
+
+        assert_records_eq(actual, expected);
+    }
+
+    /// An opening balance whose total isn't available + held fails the run.
+    #[test]
+    fn inconsistent_total() {
+        let accounts = temp_file(
+            "\
+client,available,held,total,locked
+1,100.0,10.0,100.0,false",
+        );
+
+        let path = accounts.path().to_str().unwrap();
+        let output = run_command(
+            &["--opening-balances", path, "-"],
+            "type,client,tx,amount\n",
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(!output.status.success());
+        assert!(stderr.contains("line 2"), "stderr: {stderr}");
+    }
+
+    /// Opening amounts with more decimal places than `--max-scale` fail the run, as rounding
+    /// them on output would break `total = available + held`.
+    #[test]
+    fn amounts_beyond_max_scale() {
+        let accounts = temp_file(
+            "\
+client,available,held,total,locked
+1,1.0050,1.0050,2.0100,false",
+        );
+        let output = run_command(
+            &[
+                "--max-scale",
+                "2",
+                "--opening-balances",
+                accounts.path().to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount\ndeposit,1,1,1",
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("more than 2 decimal places"),
+            "stderr: {stderr}"
+        );
+
+        let accounts = temp_file("client,available,held,total,locked\n1,1.0,0.0,1.0,false");
+        let deposits = temp_file("client,tx,amount\n1,1,0.005");
+        let output = run_command(
+            &[
+                "--max-scale",
+                "2",
+                "--opening-balances",
+                accounts.path().to_str().unwrap(),
+                "--open-deposits",
+                deposits.path().to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount\n",
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(!output.status.success());
+        assert!(stderr.contains("open deposits line 2"), "stderr: {stderr}");
+    }
+
+    /// Pre-migration deposits can be disputed, resolved and charged back.
+    #[test]
+    fn open_deposits() {
+        let accounts = temp_file(
+            "\
+client,available,held,total,locked
+1,100.0,20.0,120.0,false",
+        );
+        let deposits = temp_file(
+            "\
+client,tx,amount,under_dispute
+1,10,50.0,false
+1,11,20.0,true",
+        );
+        let input = "\
+type,client,tx,amount
+dispute,1,10,
+resolve,1,11,
+deposit,1,10,5.0
+chargeback,1,10,";
+
+        let actual = run_engine_with_args(
+            &[
+                "--opening-balances",
+                accounts.path().to_str().unwrap(),
+                "--open-deposits",
+                deposits.path().to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+        let expected = vec![ClientRecord {
+            client: 1,
+            available: dec("70.0"),
+            held: dec("0.0"),
+            total: dec("70.0"),
+            locked: true,
+        }];
+
+        assert_records_eq(actual, expected);
+    }
+}
+
+// =============================================================================
+// 15. History Command Tests
+// =============================================================================
+
+mod history {
+    use super::*;
+
+    /// Every applied and rejected transaction of the client is listed with the balances after it.
+    #[test]
+    fn applied_and_rejected() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,20.0
+deposit,2,3,5.0
+dispute,1,1,
+chargeback,1,1,
+deposit,1,4,1.0";
+
+        let stdout = run_ok(&["history", "--client", "1", "-"], input);
+
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(
+            lines[0],
+            "line,type,tx,amount,status,reason,available,held,total,locked"
+        );
+        assert_eq!(lines.len(), 6, "stdout: {stdout}");
+        assert_eq!(
+            lines[1],
+            "2,deposit,1,10.0000,applied,,10.0000,0.0000,10.0000,false"
+        );
+        assert!(lines[2].starts_with("3,withdrawal,2,20.0000,rejected,"));
+        assert!(lines[2].contains("insufficient funds"));
+        assert_eq!(
+            lines[4],
+            "6,chargeback,1,10.0000,applied,,0.0000,0.0000,0.0000,true"
+        );
+        assert!(lines[5].contains("deposit for locked account"));
+    }
+
+    /// Rejected transactions for a client without an account have no balances.
+    #[test]
+    fn unknown_client() {
+        let input = "\
+type,client,tx,amount
+withdrawal,7,1,1.0";
+
+        let stdout = run_ok(&["history", "--client", "7", "-"], input);
+
+        assert!(stdout.contains(
+            "2,withdrawal,1,1.0000,rejected,withdrawal for non existing account: ClientId(7),,,,"
+        ));
+    }
+}
+
+// =============================================================================
+// 16. Explain Command Tests
+// =============================================================================
+
+mod explain {
+    use super::*;
+
+    /// The narrative covers each transaction, rejections with their reason and the final state.
+    #[test]
+    fn locked_with_negative_available() {
+        let input = "\
+type,client,tx,amount
+deposit,7,1,10.0
+withdrawal,7,2,20.0
+withdrawal,7,3,5.0
+deposit,8,4,3.0
+dispute,7,1,
+chargeback,7,1,
+deposit,7,5,1.0";
+
+        let stdout = run_ok(&["explain", "--client", "7", "-"], input);
+
+        assert!(
+            stdout.contains("line 2: deposit 10.0000 (tx 1) was applied"),
+            "stdout: {stdout}"
+        );
+        assert!(
+            stdout.contains("line 3: withdrawal 20.0000 (tx 2) was rejected: insufficient funds"),
+            "stdout: {stdout}"
+        );
+        assert!(
+            stdout.contains("line 6: dispute of tx 1 (10.0000) was applied"),
+            "stdout: {stdout}"
+        );
+        assert!(!stdout.contains("tx 4"), "stdout: {stdout}");
+        assert!(
+            stdout.contains("Final balance: available -5.0000, held 0.0000, total -5.0000, locked"),
+            "stdout: {stdout}"
+        );
+        assert!(
+            stdout.contains("locked since the chargeback of tx 1 on line 7"),
+            "stdout: {stdout}"
+        );
+        assert!(
+            stdout.contains("disputed after being withdrawn: line 6 (tx 1)"),
+            "stdout: {stdout}"
+        );
+    }
+
+    /// A client that doesn't appear in the input is reported as such.
+    #[test]
+    fn no_transactions() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0";
+
+        let stdout = run_ok(&["explain", "--client", "2", "-"], input);
+
+        assert!(
+            stdout.contains("No transactions for client 2"),
+            "stdout: {stdout}"
+        );
+    }
+
+    /// A client with only an opening balance is explained from that balance.
+    #[test]
+    fn opening_balance_only() {
+        let accounts =
+            temp_file("client,available,held,total,locked\n2,5.0000,0.0000,5.0000,true\n");
+        let stdout = run_ok(
+            &[
+                "explain",
+                "--client",
+                "2",
+                "--opening-balances",
+                accounts.path().to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount\ndeposit,1,1,10.0",
+        );
+
+        assert_eq!(
+            stdout,
+            "No transactions for client 2 in the input.\n\
+             Opening balance: available 5.0000, held 0.0000, total 5.0000, locked\n\
+             Final balance: available 5.0000, held 0.0000, total 5.0000, locked\n\
+             The account was already locked in the opening balances.\n"
+        );
+    }
+
+    /// Transactions are explained on top of the opening balance.
+    #[test]
+    fn transactions_after_opening_balance() {
+        let accounts =
+            temp_file("client,available,held,total,locked\n1,100.0000,0.0000,100.0000,false\n");
+        let stdout = run_ok(
+            &[
+                "explain",
+                "--client",
+                "1",
+                "--opening-balances",
+                accounts.path().to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount\nwithdrawal,1,1,30.0",
+        );
+
+        assert!(
+            stdout.contains(
+                "Transactions of client 1:\n\
+                 Opening balance: available 100.0000, held 0.0000, total 100.0000\n\
+                 line 2: withdrawal 30.0000 (tx 1) was applied"
+            ),
+            "stdout: {stdout}"
+        );
+    }
+}
+
+// =============================================================================
+// 17. Balance Event Stream Tests
+// =============================================================================
+
+mod events {
+    use super::*;
+
+    /// Every applied transaction emits one event with balances before and after it.
+    #[test]
+    fn applied_transactions_only() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,20.0
+dispute,1,1,
+chargeback,1,1,";
+
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("events.ndjson");
+        run_engine_with_args(&["--events", path.to_str().unwrap(), "-"], input);
+
+        let content = std::fs::read_to_string(&path).expect("Failed to read events");
+        let events: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Invalid JSON"))
+            .collect();
+
+        assert_eq!(events.len(), 3, "events: {content}");
+        assert_eq!(events[0]["operation"], "deposit");
+        assert_eq!(events[0]["before"]["total"], "0.0000");
+        assert_eq!(events[0]["after"]["available"], "10.0000");
+        assert_eq!(events[1]["operation"], "dispute");
+        assert_eq!(events[1]["line"], 4);
+        assert_eq!(events[1]["after"]["held"], "10.0000");
+        assert_eq!(events[2]["client"], 1);
+        assert_eq!(events[2]["tx"], 1);
+        assert_eq!(events[2]["before"]["locked"], false);
+        assert_eq!(events[2]["after"]["locked"], true);
+        assert_eq!(events[2]["after"]["total"], "0.0000");
+    }
+}
+
+// =============================================================================
+// 18. Point-in-time Tests
+// =============================================================================
+
+mod point_in_time {
+    use super::*;
+
+    const INPUT: &str = "\
+type,client,tx,amount,timestamp
+deposit,1,1,10.0,1700000000
+deposit,2,2,5.0,1700000100
+withdrawal,1,3,4.0,1700086400
+dispute,2,2,,1700090000
+deposit,1,4,1.0,1700172800";
+
+    /// `--as-of line:N` stops after the row on line N.
+    #[test]
+    fn as_of_line() {
+        let output = run_engine_with_args(&["--as-of", "line:3", "-"], INPUT);
+
+        assert_records_eq(
+            output,
+            vec![
+                ClientRecord {
+                    client: 1,
+                    available: dec("10.0000"),
+                    held: dec("0.0000"),
+                    total: dec("10.0000"),
+                    locked: false,
+                },
+                ClientRecord {
+                    client: 2,
+                    available: dec("5.0000"),
+                    held: dec("0.0000"),
+                    total: dec("5.0000"),
+                    locked: false,
+                },
+            ],
+        );
+    }
+
+    /// `--as-of time:T` includes every row up to timestamp T.
+    #[test]
+    fn as_of_time() {
+        let output = run_engine_with_args(&["--as-of", "time:1700090000", "-"], INPUT);
+
+        assert_records_eq(
+            output,
+            vec![
+                ClientRecord {
+                    client: 1,
+                    available: dec("6.0000"),
+                    held: dec("0.0000"),
+                    total: dec("6.0000"),
+                    locked: false,
+                },
+                ClientRecord {
+                    client: 2,
+                    available: dec("0.0000"),
+                    held: dec("5.0000"),
+                    total: dec("5.0000"),
+                    locked: false,
+                },
+            ],
+        );
+    }
+
+    /// Input without a timestamp column is processed as before.
+    #[test]
+    fn timestamp_column_optional() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,5.0";
+
+        let output = run_engine_with_args(&["--as-of", "time:0", "-"], input);
+
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].total, dec("15.0000"));
+    }
+
+    #[test]
+    fn invalid_as_of() {
+        let output = run_command(&["--as-of", "row:3", "-"], INPUT);
+
+        assert!(!output.status.success());
+    }
+
+    /// The library query returns the balances at the requested line.
+    #[test]
+    fn library_query() {
+        use yet_another_transactions_processor::engine::{Ledger, Processor};
+        use yet_another_transactions_processor::limits::Limits;
+        use yet_another_transactions_processor::point_in_time::{AsOf, balances_as_of};
+        use yet_another_transactions_processor::precision::Precision;
+
+        let mut processor = Processor::new(Ledger::new(), Precision::default(), Limits::default());
+        let mut reader = csv::Reader::from_reader(INPUT.as_bytes());
+        let mut records = balances_as_of(&mut processor, &mut reader, AsOf::Line(4)).unwrap();
+        records.sort_by_key(|record| record.client.0);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].available, dec("6.0000"));
+        assert_eq!(records[1].held, dec("0.0000"));
+    }
+
+    /// Snapshots every N rows are labelled with the last line they include.
+    #[test]
+    fn snapshots_every_rows() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("snapshots.csv");
+        run_engine_with_args(
+            &[
+                "--snapshots",
+                path.to_str().unwrap(),
+                "--snapshot-every",
+                "2",
+                "-",
+            ],
+            INPUT,
+        );
+
+        let content = std::fs::read_to_string(&path).expect("Failed to read snapshots");
+        assert_eq!(
+            content,
+            "\
+as_of,client,available,held,total,locked
+line:3,1,10.0000,0.0000,10.0000,false
+line:3,2,5.0000,0.0000,5.0000,false
+line:5,1,6.0000,0.0000,6.0000,false
+line:5,2,0.0000,5.0000,5.0000,false
+line:6,1,7.0000,0.0000,7.0000,false
+line:6,2,0.0000,5.0000,5.0000,false
+"
+        );
+    }
+
+    /// Daily snapshots hold the balances at the end of each day with transactions.
+    #[test]
+    fn snapshots_per_day() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("snapshots.csv");
+        run_engine_with_args(
+            &[
+                "--snapshots",
+                path.to_str().unwrap(),
+                "--snapshot-every",
+                "day",
+                "-",
+            ],
+            INPUT,
+        );
+
+        let content = std::fs::read_to_string(&path).expect("Failed to read snapshots");
+        assert_eq!(
+            content,
+            "\
+as_of,client,available,held,total,locked
+2023-11-14,1,10.0000,0.0000,10.0000,false
+2023-11-14,2,5.0000,0.0000,5.0000,false
+2023-11-15,1,6.0000,0.0000,6.0000,false
+2023-11-15,2,0.0000,5.0000,5.0000,false
+2023-11-16,1,7.0000,0.0000,7.0000,false
+2023-11-16,2,0.0000,5.0000,5.0000,false
+"
+        );
+    }
+}
+
+// =============================================================================
+// 19. Open Disputes Report Tests
+// =============================================================================
+
+mod open_disputes {
+    use super::*;
+
+    /// Only disputes that are neither resolved nor charged back are listed, with their age.
+    #[test]
+    fn lists_open_disputes() {
+        let input = "\
+type,client,tx,amount,timestamp
+deposit,1,1,10.0,1000
+deposit,1,2,5.0,1010
+deposit,2,3,7.0,1020
+dispute,1,1,,1100
+dispute,1,2,,1200
+dispute,2,3,,1300
+resolve,1,2,,1400
+deposit,2,4,1.0,1500";
+
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let list = dir.path().join("disputes.csv");
+        let totals = dir.path().join("totals.csv");
+        run_engine_with_args(
+            &[
+                "--open-disputes",
+                list.to_str().unwrap(),
+                "--open-dispute-totals",
+                totals.to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+
+        let list = std::fs::read_to_string(&list).expect("Failed to read report");
+        assert_eq!(
+            list,
+            "\
+client,tx,amount,line,open_rows,open_seconds
+1,1,10.0000,5,4,400
+2,3,7.0000,7,2,200
+"
+        );
+        let totals = std::fs::read_to_string(&totals).expect("Failed to read totals");
+        assert_eq!(
+            totals,
+            "\
+client,disputes,amount,held
+1,1,10.0000,10.0000
+2,1,7.0000,7.0000
+"
+        );
+    }
+
+    /// Disputes carried over in the open deposits are reported without an opening line.
+    #[test]
+    fn carried_over_disputes() {
+        let balances = temp_file("client,available,held,total,locked\n1,0.0,3.0,3.0,false\n");
+        let deposits = temp_file("client,tx,amount,under_dispute\n1,9,3.0,true\n");
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0";
+
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let list = dir.path().join("disputes.csv");
+        run_engine_with_args(
+            &[
+                "--opening-balances",
+                balances.path().to_str().unwrap(),
+                "--open-deposits",
+                deposits.path().to_str().unwrap(),
+                "--open-disputes",
+                list.to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+
+        let list = std::fs::read_to_string(&list).expect("Failed to read report");
+        assert_eq!(
+            list,
+            "\
+client,tx,amount,line,open_rows,open_seconds
+1,9,3.0000,,,
+"
+        );
+    }
+}
+
+// =============================================================================
+// 20. Risk Report Tests
+// =============================================================================
+
+mod risk_report {
+    use super::*;
+
+    const INPUT: &str = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,5.0
+deposit,1,3,5.0
+deposit,1,4,5.0
+dispute,1,1,
+resolve,1,1,
+deposit,2,5,8.0
+dispute,2,5,
+chargeback,2,5,
+deposit,3,6,1.0
+dispute,3,9,";
+
+    fn risk_report(args: &[&str]) -> String {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("risk.csv");
+        let mut all_args = vec!["--risk-report", path.to_str().unwrap()];
+        all_args.extend_from_slice(args);
+        all_args.push("-");
+        run_engine_with_args(&all_args, INPUT);
+        std::fs::read_to_string(&path).expect("Failed to read report")
+    }
+
+    /// Only applied transactions are counted; clients above the default 1% are flagged.
+    #[test]
+    fn default_thresholds() {
+        assert_eq!(
+            risk_report(&[]),
+            "\
+client,deposits,deposit_amount,disputes,dispute_amount,resolves,resolve_amount,chargebacks,chargeback_amount,dispute_ratio,chargeback_ratio,flagged
+1,4,25.0000,1,10.0000,1,10.0000,0,0.0000,0.2500,0.0000,true
+2,1,8.0000,1,8.0000,0,0.0000,1,8.0000,1.0000,1.0000,true
+3,1,1.0000,0,0.0000,0,0.0000,0,0.0000,0.0000,0.0000,false
+"
+        );
+    }
+
+    #[test]
+    fn custom_thresholds() {
+        let report = risk_report(&[
+            "--dispute-ratio-threshold",
+            "0.5",
+            "--chargeback-ratio-threshold",
+            "0.5",
+        ]);
+        let flags: Vec<&str> = report
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplit(',').next().unwrap())
+            .collect();
+
+        assert_eq!(flags, ["false", "true", "false"]);
+    }
+
+    #[test]
+    fn thresholds_require_report() {
+        let output = run_command(&["--dispute-ratio-threshold", "0.5", "-"], INPUT);
+
+        assert!(!output.status.success());
+    }
+}
+
+// =============================================================================
+// 21. Invariant Verification Tests
+// =============================================================================
+
+mod verify {
+    use super::*;
+
+    /// A run mixing every transaction type, rejections and negative available passes.
+    #[test]
+    fn consistent_run_passes() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,8.0
+dispute,1,1,
+deposit,2,3,5.0
+deposit,2,4,3.0
+dispute,2,3,
+dispute,2,4,
+resolve,2,4,
+chargeback,2,3,
+deposit,2,5,1.0
+withdrawal,3,6,1.0
+chargeback,1,1,";
+
+        let output = run_ok(&["--verify", "-"], input);
+
+        assert_eq!(parse_output(&output).len(), 2);
+    }
+
+    /// Held balances carried over without a matching open deposit stay accounted for.
+    #[test]
+    fn opening_balances_pass() {
+        let balances = temp_file(
+            "client,available,held,total,locked\n1,2.0,5.0,7.0,false\n2,1.0,0.0,1.0,true\n",
+        );
+        let deposits = temp_file("client,tx,amount,under_dispute\n1,9,3.0,true\n");
+        let input = "\
+type,client,tx,amount
+resolve,1,9,
+deposit,1,1,4.0
+withdrawal,1,2,6.0";
+
+        run_ok(
+            &[
+                "--verify",
+                "--opening-balances",
+                balances.path().to_str().unwrap(),
+                "--open-deposits",
+                deposits.path().to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+    }
+}
+
+// =============================================================================
+// 22. Journal Tests
+// =============================================================================
+
+mod journal {
+    use super::*;
+
+    /// Every applied transaction is one debit and one credit line; rejected ones are skipped.
+    #[test]
+    fn balanced_entries() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,3.0
+withdrawal,1,3,30.0
+dispute,1,1,
+resolve,1,1,
+dispute,1,1,
+chargeback,1,1,";
+
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("journal.csv");
+        run_engine_with_args(&["--journal", path.to_str().unwrap(), "-"], input);
+
+        let content = std::fs::read_to_string(&path).expect("Failed to read journal");
+        assert_eq!(
+            content,
+            "\
+line,tx,operation,account,debit,credit
+2,1,deposit,cash,10.0000,
+2,1,deposit,client:1:available,,10.0000
+3,2,withdrawal,client:1:available,3.0000,
+3,2,withdrawal,cash,,3.0000
+5,1,dispute,client:1:available,10.0000,
+5,1,dispute,client:1:held,,10.0000
+6,1,resolve,client:1:held,10.0000,
+6,1,resolve,client:1:available,,10.0000
+7,1,dispute,client:1:available,10.0000,
+7,1,dispute,client:1:held,,10.0000
+8,1,chargeback,client:1:held,10.0000,
+8,1,chargeback,chargebacks,,10.0000
+"
+        );
+    }
+
+    /// The postings check starts from the opening balances and covers fees and transfers.
+    #[test]
+    fn postings_match_ledger() {
+        let accounts = temp_file(
+            "client,available,held,total,locked\n\
+             1,100.0000,5.0000,105.0000,false\n\
+             2,0.0000,0.0000,0.0000,false\n\
+             100,0.0000,0.0000,0.0000,false\n",
+        );
+        let schedule = temp_file("house_account = 100\n[[tier]]\nflat = \"0.5\"\n");
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("journal.csv");
+        let input = "\
+type,client,tx,amount,destination
+withdrawal,1,1,10.0,
+transfer,1,2,20.0,2
+deposit,2,3,1.0,";
+
+        run_ok(
+            &[
+                "--opening-balances",
+                accounts.path().to_str().unwrap(),
+                "--fees",
+                schedule.path().to_str().unwrap(),
+                "--journal",
+                path.to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+        let content = std::fs::read_to_string(&path).expect("Failed to read journal");
+        assert!(
+            content.contains("2,1,fee,client:100:available,,0.5000\n")
+                && content.contains("3,2,transfer,client:2:available,,20.0000\n"),
+            "journal: {content}"
+        );
+    }
+}
+
+// =============================================================================
+// 23. Accounts Diff Tests
+// =============================================================================
+
+mod diff {
+    use super::*;
+
+    const LEFT: &str = "\
+client,available,held,total,locked
+1,10.0000,0.0000,10.0000,false
+2,5.0000,1.0000,6.0000,false
+3,1.0000,0.0000,1.0000,false
+";
+
+    fn run_diff(args: &[&str], right: &str) -> std::process::Output {
+        let left = temp_file(LEFT);
+        let right = temp_file(right);
+        let mut all_args = vec!["diff"];
+        all_args.extend_from_slice(args);
+        all_args.push(left.path().to_str().unwrap());
+        all_args.push(right.path().to_str().unwrap());
+        run_command(&all_args, "")
+    }
+
+    #[test]
+    fn identical_files() {
+        let output = run_diff(
+            &[],
+            "client,available,held,total,locked\n3,1.0,0,1.0,false\n2,5.0,1.0,6.0,false\n1,10,0,10,false\n",
+        );
+
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "No differences.\n"
+        );
+    }
+
+    /// Added and removed clients and changed fields are reported and the exit code is 1.
+    #[test]
+    fn reports_differences() {
+        let right = "\
+client,available,held,total,locked
+1,9.5000,0.0000,9.5000,false
+2,5.0000,1.0000,6.0000,true
+4,1.0000,0.0000,1.0000,false
+";
+
+        let output = run_diff(&[], right);
+
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "\
+- client 3
++ client 4
+~ client 1: available 10.0000 -> 9.5000 (diff -0.5000)
+~ client 1: total 10.0000 -> 9.5000 (diff -0.5000)
+~ client 2: locked false -> true
+5 differences.
+"
+        );
+    }
+
+    #[test]
+    fn tolerance() {
+        let right = "\
+client,available,held,total,locked
+1,10.0050,0.0000,10.0050,false
+2,5.0000,1.0000,6.0000,false
+3,1.0000,0.0000,1.0000,false
+";
+
+        assert!(run_diff(&["--tolerance", "0.01"], right).status.success());
+        assert_eq!(
+            run_diff(&["--tolerance", "0.001"], right).status.code(),
+            Some(1)
+        );
+    }
+
+    /// Files that can't be compared exit with 2, unlike files that differ.
+    #[test]
+    fn errors_exit_with_2() {
+        let overflowing = "\
+client,available,held,total,locked
+1,-79228162514264337593543950335,0.0000,10.0000,false
+";
+
+        let output = run_diff(&[], overflowing);
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("available difference overflows"),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let missing = run_command(&["diff", "missing-left.csv", "missing-right.csv"], "");
+        assert_eq!(missing.status.code(), Some(2));
+    }
+}
+
+// =============================================================================
+// 24. What-if Policy Tests
+// =============================================================================
+
+mod what_if {
+    use super::*;
+
+    const INPUT: &str = "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,8.0
+dispute,1,1,
+deposit,2,3,5.0
+deposit,2,4,1.0
+dispute,2,3,
+chargeback,2,3,
+dispute,2,4,";
+
+    fn what_if(args: &[&str]) -> String {
+        let input = temp_file(INPUT);
+        let mut all_args = vec!["what-if"];
+        all_args.extend_from_slice(args);
+        all_args.push(input.path().to_str().unwrap());
+        run_ok(&all_args, "")
+    }
+
+    #[test]
+    fn forbid_negative_available() {
+        assert_eq!(
+            what_if(&["--forbid-negative-available"]),
+            "\
+Balances (baseline -> alternative):
+~ client 1: available -8.0000 -> 2.0000 (diff 10.0000)
+~ client 1: held 10.0000 -> 0.0000 (diff -10.0000)
+2 differences.
+Transactions:
+line 4: dispute of tx 1 for client 1: rejected only by the alternative: insufficient funds (available: 2.0000, requested: 10.0000): ClientId(1)
+1 transactions with different outcomes.
+"
+        );
+    }
+
+    #[test]
+    fn allow_disputes_on_locked() {
+        assert_eq!(
+            what_if(&["--allow-disputes-on-locked"]),
+            "\
+Balances (baseline -> alternative):
+~ client 2: available 1.0000 -> 0.0000 (diff -1.0000)
+~ client 2: held 0.0000 -> 1.0000 (diff 1.0000)
+2 differences.
+Transactions:
+line 9: dispute of tx 4 for client 2: rejected only by the baseline: dispute for locked account: ClientId(2)
+1 transactions with different outcomes.
+"
+        );
+    }
+
+    /// Rows rejected by both runs for the same reason aren't listed, even with different
+    /// amounts in the rejections.
+    #[test]
+    fn same_reason_with_different_amounts() {
+        let input = temp_file(&format!("{INPUT}\nwithdrawal,1,5,50.0"));
+        let stdout = run_ok(
+            &[
+                "what-if",
+                "--forbid-negative-available",
+                input.path().to_str().unwrap(),
+            ],
+            "",
+        );
+
+        assert!(
+            stdout.ends_with("1 transactions with different outcomes.\n"),
+            "stdout: {stdout}"
+        );
+        assert!(!stdout.contains("line 10"), "stdout: {stdout}");
+    }
+
+    #[test]
+    fn requires_policy_change() {
+        let input = temp_file(INPUT);
+        let output = run_command(&["what-if", input.path().to_str().unwrap()], "");
+
+        assert!(!output.status.success());
+    }
+}
+
+// =============================================================================
+// 25. Policy File Tests
+// =============================================================================
+
+mod policy {
+    use super::*;
+
+    fn run_with_policy(policy: &str, input: &str) -> Vec<ClientRecord> {
+        let policy = temp_file(policy);
+        run_engine_with_args(&["--policy", policy.path().to_str().unwrap(), "-"], input)
+    }
+
+    /// Any transaction opens an account when deposits aren't the only way to create one.
+    #[test]
+    fn any_transaction_creates_account() {
+        let input = "\
+type,client,tx,amount
+withdrawal,1,1,5.0
+dispute,2,2,";
+
+        assert!(run_engine(input).is_empty());
+        let output = run_with_policy("only_deposits_create_accounts = false\n", input);
+
+        assert_eq!(output.len(), 2);
+        assert!(output.iter().all(|record| record.total == dec("0.0000")));
+    }
+
+    #[test]
+    fn chargebacks_on_locked_accounts() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,5.0
+dispute,1,1,
+dispute,1,2,
+chargeback,1,1,
+chargeback,1,2,";
+
+        let output = run_with_policy("locked_rejects_disputes = false\n", input);
+
+        assert_records_eq(
+            output,
+            vec![ClientRecord {
+                client: 1,
+                available: dec("0.0000"),
+                held: dec("0.0000"),
+                total: dec("0.0000"),
+                locked: true,
+            }],
+        );
+    }
+
+    #[test]
+    fn no_redispute_after_resolve() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+dispute,1,1,
+resolve,1,1,
+dispute,1,1,";
+
+        let output = run_with_policy("redispute_resolved = false\n", input);
+
+        assert_eq!(output[0].available, dec("10.0000"));
+        assert_eq!(output[0].held, dec("0.0000"));
+    }
+
+    #[test]
+    fn unknown_rule_rejected() {
+        let policy = temp_file("no_such_rule = true\n");
+        let output = run_command(
+            &["--policy", policy.path().to_str().unwrap(), "-"],
+            "type,client,tx,amount\n",
+        );
+
+        assert!(!output.status.success());
+    }
+
+    /// The metadata echoes the active policy, including rules left at their default.
+    #[test]
+    fn policy_in_metadata() {
+        let policy = temp_file("dispute_shortfall = \"reject\"\n");
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("metadata.json");
+        run_engine_with_args(
+            &[
+                "--policy",
+                policy.path().to_str().unwrap(),
+                "--metadata",
+                path.to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,2.0\n",
+        );
+
+        let content = std::fs::read_to_string(&path).expect("Failed to read metadata");
+        let metadata: serde_json::Value = serde_json::from_str(&content).expect("Invalid JSON");
+        assert_eq!(metadata["input"], "-");
+        assert_eq!(metadata["policy"]["dispute_shortfall"], "reject");
+        assert_eq!(metadata["policy"]["redispute_resolved"], true);
+        assert_eq!(metadata["rows"], 2);
+        assert_eq!(metadata["rejected"], 1);
+        assert_eq!(metadata["clients"], 1);
+    }
+}
+
+// =============================================================================
+// 26. Dispute Shortfall Tests
+// =============================================================================
+
+mod dispute_shortfall {
+    use super::*;
+
+    /// Runs the engine under the given `dispute_shortfall` rule and returns the raw output.
+    fn run_with_shortfall(shortfall: &str, input: &str) -> String {
+        let policy = temp_file(&format!("dispute_shortfall = \"{shortfall}\"\n"));
+        run_ok(
+            &["--policy", policy.path().to_str().unwrap(), "--verify", "-"],
+            input,
+        )
+    }
+
+    const PARTLY_WITHDRAWN: &str = "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,6.0
+dispute,1,1,";
+
+    #[test]
+    fn reject_dispute_beyond_available() {
+        let output = run_with_shortfall("reject", PARTLY_WITHDRAWN);
+
+        assert_records_eq(
+            parse_output(&output),
+            vec![ClientRecord {
+                client: 1,
+                available: dec("4.0000"),
+                held: dec("0.0000"),
+                total: dec("4.0000"),
+                locked: false,
+            }],
+        );
+    }
+
+    #[test]
+    fn debt_holds_what_is_available() {
+        let output = run_with_shortfall("debt", PARTLY_WITHDRAWN);
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,debt\n1,0.0000,4.0000,4.0000,false,6.0000\n"
+        );
+    }
+
+    /// Later deposits pay off the debt before adding to available.
+    #[test]
+    fn deposits_pay_off_debt() {
+        let input = format!("{PARTLY_WITHDRAWN}\ndeposit,1,3,3.0\ndeposit,1,4,5.0");
+        let output = run_with_shortfall("debt", &input);
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,debt\n1,2.0000,4.0000,6.0000,false,0.0000\n"
+        );
+    }
+
+    /// Resolving the dispute cancels its debt and returns what was already paid off of it.
+    #[test]
+    fn resolve_cancels_debt() {
+        let input = format!("{PARTLY_WITHDRAWN}\ndeposit,1,3,2.0\nresolve,1,1,");
+        let output = run_with_shortfall("debt", &input);
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,debt\n1,6.0000,0.0000,6.0000,false,0.0000\n"
+        );
+    }
+
+    #[test]
+    fn chargeback_keeps_debt() {
+        let input = format!("{PARTLY_WITHDRAWN}\nchargeback,1,1,");
+        let output = run_with_shortfall("debt", &input);
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,debt\n1,0.0000,0.0000,0.0000,true,6.0000\n"
+        );
+    }
+
+    /// The debt a dispute leaves and its repayment get their own journal entries.
+    #[test]
+    fn debt_in_journal() {
+        let policy = temp_file("dispute_shortfall = \"debt\"\n");
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("journal.csv");
+        let input = format!(
+            "{PARTLY_WITHDRAWN}\ndeposit,1,3,2.0\nresolve,1,1,\ndispute,1,1,\nchargeback,1,1,"
+        );
+        run_ok(
+            &[
+                "--policy",
+                policy.path().to_str().unwrap(),
+                "--journal",
+                path.to_str().unwrap(),
+                "-",
+            ],
+            &input,
+        );
+        let content = std::fs::read_to_string(&path).expect("Failed to read journal");
+        assert_eq!(
+            content,
+            "\
+line,tx,operation,account,debit,credit
+2,1,deposit,cash,10.0000,
+2,1,deposit,client:1:available,,10.0000
+3,2,withdrawal,client:1:available,6.0000,
+3,2,withdrawal,cash,,6.0000
+4,1,dispute,client:1:available,4.0000,
+4,1,dispute,client:1:held,,4.0000
+4,1,dispute,client:1:debt,6.0000,
+4,1,dispute,client:1:shortfall,,6.0000
+5,3,deposit,cash,2.0000,
+5,3,deposit,client:1:debt,,2.0000
+6,1,resolve,client:1:held,4.0000,
+6,1,resolve,client:1:available,,4.0000
+6,1,resolve,client:1:shortfall,2.0000,
+6,1,resolve,client:1:available,,2.0000
+6,1,resolve,client:1:shortfall,4.0000,
+6,1,resolve,client:1:debt,,4.0000
+7,1,dispute,client:1:available,6.0000,
+7,1,dispute,client:1:held,,6.0000
+7,1,dispute,client:1:debt,4.0000,
+7,1,dispute,client:1:shortfall,,4.0000
+8,1,chargeback,client:1:held,6.0000,
+8,1,chargeback,chargebacks,,6.0000
+8,1,chargeback,client:1:shortfall,4.0000,
+8,1,chargeback,chargebacks,,4.0000
+"
+        );
+    }
+
+    /// Open dispute totals add up disputed amounts beyond what could be held without
+    /// overflowing.
+    #[test]
+    fn open_dispute_totals_beyond_held() {
+        let policy = temp_file("dispute_shortfall = \"debt\"\n");
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("totals.csv");
+        let input = "\
+type,client,tx,amount
+deposit,1,1,500000000000000
+withdrawal,1,2,500000000000000
+dispute,1,1,
+deposit,1,3,500000000000000
+dispute,1,3,";
+        run_ok(
+            &[
+                "--policy",
+                policy.path().to_str().unwrap(),
+                "--open-dispute-totals",
+                path.to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+        let content = std::fs::read_to_string(&path).expect("Failed to read totals");
+        assert_eq!(
+            content,
+            "client,disputes,amount,held\n1,2,1000000000000000.0000,0.0000\n"
+        );
+    }
+}
+
+// =============================================================================
+// 27. Withdrawal Fee Tests
+// =============================================================================
+
+mod withdrawal_fees {
+    use super::*;
+
+    const SCHEDULE: &str = "\
+house_account = 100
+
+[[tier]]
+flat = \"0.5\"
+
+[[tier]]
+from = \"100\"
+percent = \"1.5\"
+min = \"2\"
+max = \"10\"
+";
+
+    /// The house account needs an account of its own to take fees.
+    const HOUSE: &str = "client,available,held,total,locked\n100,0.0000,0.0000,0.0000,false\n";
+
+    /// Runs the engine with the given fee schedule and an open house account and returns the
+    /// raw output, sorted by client.
+    fn run_with_fees(schedule: &str, input: &str) -> String {
+        let schedule = temp_file(schedule);
+        let house = temp_file(HOUSE);
+        sorted_rows(&run_ok(
+            &[
+                "--fees",
+                schedule.path().to_str().unwrap(),
+                "--opening-balances",
+                house.path().to_str().unwrap(),
+                "--verify",
+                "-",
+            ],
+            input,
+        ))
+    }
+
+    #[test]
+    fn flat_fee_below_first_percentage_tier() {
+        let output = run_with_fees(
+            SCHEDULE,
+            "type,client,tx,amount\ndeposit,1,1,50.0\nwithdrawal,1,2,10.0",
+        );
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,fees\n\
+             1,39.5000,0.0000,39.5000,false,0.5000\n\
+             100,0.5000,0.0000,0.5000,false,0.0000"
+        );
+    }
+
+    /// Percentage fees are kept between the tier's min and max.
+    #[test]
+    fn percentage_fee_within_min_and_max() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,2000.0
+withdrawal,1,2,100.0
+withdrawal,1,3,200.0
+withdrawal,1,4,700.0";
+        let output = run_with_fees(SCHEDULE, input);
+
+        // Fees of 2 (min), 3 and 10 (max).
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,fees\n\
+             1,985.0000,0.0000,985.0000,false,15.0000\n\
+             100,15.0000,0.0000,15.0000,false,0.0000"
+        );
+    }
+
+    #[test]
+    fn insufficient_funds_include_fee() {
+        let output = run_with_fees(
+            SCHEDULE,
+            "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,10.0",
+        );
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,fees\n\
+             1,10.0000,0.0000,10.0000,false,0.0000\n\
+             100,0.0000,0.0000,0.0000,false,0.0000"
+        );
+    }
+
+    #[test]
+    fn fees_rounded_to_max_scale() {
+        let schedule = temp_file("house_account = 100\n[[tier]]\npercent = \"0.5\"\n");
+        let house = temp_file(HOUSE);
+        let output = run_engine_with_args(
+            &[
+                "--fees",
+                schedule.path().to_str().unwrap(),
+                "--opening-balances",
+                house.path().to_str().unwrap(),
+                "--max-scale",
+                "2",
+                "-",
+            ],
+            "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,1.01",
+        );
+
+        let client = output.iter().find(|record| record.client == 1).unwrap();
+        assert_eq!(client.available, dec("8.98"));
+    }
+
+    #[test]
+    fn invalid_schedule_rejected() {
+        let schedule = temp_file("house_account = 100\n[[tier]]\npercent = \"150\"\n");
+        let output = run_command(
+            &["--fees", schedule.path().to_str().unwrap(), "-"],
+            "type,client,tx,amount\n",
+        );
+
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn fees_in_journal() {
+        let schedule = temp_file(SCHEDULE);
+        let house = temp_file(HOUSE);
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("journal.csv");
+        run_engine_with_args(
+            &[
+                "--fees",
+                schedule.path().to_str().unwrap(),
+                "--opening-balances",
+                house.path().to_str().unwrap(),
+                "--journal",
+                path.to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount\ndeposit,1,1,50.0\nwithdrawal,1,2,10.0",
+        );
+
+        let journal = std::fs::read_to_string(&path).expect("Failed to read journal");
+        assert!(journal.ends_with(
+            "3,2,fee,client:1:available,0.5000,\n3,2,fee,client:100:available,,0.5000\n"
+        ));
+    }
+
+    /// Runs the engine with the given fee schedule and extra arguments, without opening the
+    /// house account, and returns the records.
+    fn run_with_house(args: &[&str], input: &str) -> Vec<ClientRecord> {
+        let schedule = temp_file(SCHEDULE);
+        let mut all_args = vec!["--fees", schedule.path().to_str().unwrap()];
+        all_args.extend_from_slice(args);
+        all_args.push("-");
+        run_engine_with_args(&all_args, input)
+    }
+
+    /// Only deposits open accounts, so a fee can't open the house account.
+    #[test]
+    fn fee_requires_house_account() {
+        let input = "type,client,tx,amount\ndeposit,1,1,50.0\nwithdrawal,1,2,10.0";
+        let output = run_with_house(&[], input);
+
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].available, dec("50.0000"));
+
+        let policy = temp_file("only_deposits_create_accounts = false\n");
+        let output = run_with_house(&["--policy", policy.path().to_str().unwrap()], input);
+
+        assert_eq!(output.len(), 2);
+    }
+
+    /// A locked house account, or one that would go over the max balance, rejects the
+    /// withdrawal.
+    #[test]
+    fn house_account_checked() {
+        let input = "type,client,tx,amount\ndeposit,1,1,50.0\nwithdrawal,1,2,10.0";
+        let locked =
+            temp_file("client,available,held,total,locked\n100,0.0000,0.0000,0.0000,true\n");
+        let output = run_with_house(
+            &["--opening-balances", locked.path().to_str().unwrap()],
+            input,
+        );
+        let client = output.iter().find(|record| record.client == 1).unwrap();
+        assert_eq!(client.available, dec("50.0000"));
+
+        let full =
+            temp_file("client,available,held,total,locked\n100,60.0000,0.0000,60.0000,false\n");
+        let output = run_with_house(
+            &[
+                "--opening-balances",
+                full.path().to_str().unwrap(),
+                "--max-balance",
+                "60",
+            ],
+            input,
+        );
+        let client = output.iter().find(|record| record.client == 1).unwrap();
+        assert_eq!(client.available, dec("50.0000"));
+    }
+
+    /// The house account's side of a fee shows up in events and in its history.
+    #[test]
+    fn house_in_events_and_history() {
+        let schedule = temp_file(SCHEDULE);
+        let house = temp_file(HOUSE);
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let events = dir.path().join("events.jsonl");
+        let input = "type,client,tx,amount\ndeposit,1,1,50.0\nwithdrawal,1,2,10.0";
+        run_engine_with_args(
+            &[
+                "--fees",
+                schedule.path().to_str().unwrap(),
+                "--opening-balances",
+                house.path().to_str().unwrap(),
+                "--events",
+                events.to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+
+        let content = std::fs::read_to_string(&events).expect("Failed to read events");
+        let last: serde_json::Value =
+            serde_json::from_str(content.lines().last().unwrap()).expect("Invalid JSON");
+        assert_eq!(last["client"], 100);
+        assert_eq!(last["operation"], "fee");
+        assert_eq!(last["before"]["available"], "0.0000");
+        assert_eq!(last["after"]["available"], "0.5000");
+
+        let output = run_command(
+            &[
+                "history",
+                "--client",
+                "100",
+                "--fees",
+                schedule.path().to_str().unwrap(),
+                "--opening-balances",
+                house.path().to_str().unwrap(),
+                "-",
+            ],
+            input,
+        );
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "line,type,tx,amount,status,reason,available,held,total,locked\n\
+             3,fee,2,0.5000,applied,,0.5000,0.0000,0.5000,false\n"
+        );
+    }
+}
+
+// =============================================================================
+// 28. Overdraft Limit Tests
+// =============================================================================
+
+mod overdraft_limits {
+    use super::*;
+
+    const LIMITS: &str = "client,allowance,max_withdrawal\n1,50,\n2,100,20\n";
+
+    fn run_with_limits(limits: &str, input: &str) -> Vec<ClientRecord> {
+        let limits = temp_file(limits);
+        run_engine_with_args(
+            &["--overdraft-limits", limits.path().to_str().unwrap(), "-"],
+            input,
+        )
+    }
+
+    #[test]
+    fn withdrawal_within_allowance() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,55.0
+withdrawal,1,3,6.0";
+
+        let output = run_with_limits(LIMITS, input);
+
+        assert_eq!(output[0].available, dec("-45.0000"));
+    }
+
+    #[test]
+    fn max_single_withdrawal() {
+        let input = "\
+type,client,tx,amount
+deposit,2,1,10.0
+withdrawal,2,2,25.0
+withdrawal,2,3,20.0";
+
+        let output = run_with_limits(LIMITS, input);
+
+        assert_eq!(output[0].available, dec("-10.0000"));
+    }
+
+    /// Clients without a limit can't overdraw.
+    #[test]
+    fn no_allowance_without_limit() {
+        let input = "type,client,tx,amount\ndeposit,3,1,10.0\nwithdrawal,3,2,11.0";
+
+        let output = run_with_limits(LIMITS, input);
+
+        assert_eq!(output[0].available, dec("10.0000"));
+    }
+
+    #[test]
+    fn overdraft_used_column() {
+        let limits = temp_file(LIMITS);
+        let output = run_command(
+            &["--overdraft-limits", limits.path().to_str().unwrap(), "-"],
+            "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,30.0",
+        );
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "client,available,held,total,locked,overdraft_used\n\
+             1,-20.0000,0.0000,-20.0000,false,20.0000\n"
+        );
+    }
+
+    #[test]
+    fn duplicate_client_rejected() {
+        let limits = temp_file("client,allowance\n1,50\n1,10\n");
+        let output = run_command(
+            &["--overdraft-limits", limits.path().to_str().unwrap(), "-"],
+            "type,client,tx,amount\n",
+        );
+
+        assert!(!output.status.success());
+    }
+}
+
+// =============================================================================
+// 29. Velocity Limit Tests
+// =============================================================================
+
+mod velocity_limits {
+    use super::*;
+
+    const LIMITS: &str = "\
+[[limit]]
+operation = \"withdrawal\"
+window_seconds = 86400
+max_count = 2
+max_amount = \"100\"
+
+[[limit]]
+operation = \"deposit\"
+window_rows = 3
+max_count = 1
+";
+
+    /// Runs the binary with the limits above added to the given arguments and returns its
+    /// output.
+    fn run_with_limits(args: &[&str], input: &str) -> String {
+        let limits = temp_file(LIMITS);
+        let mut all_args = args.to_vec();
+        all_args.extend(["--velocity-limits", limits.path().to_str().unwrap()]);
+        run_ok(&all_args, input)
+    }
+
+    #[test]
+    fn deposits_per_row_window() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,5.0
+deposit,1,3,5.0
+deposit,1,4,5.0";
+
+        let output = run_with_limits(&["-"], input);
+
+        let records = parse_output(&output);
+        assert_eq!(records[0].available, dec("15.0000"));
+    }
+
+    /// Withdrawals within a day count towards the same limits, older ones drop out.
+    #[test]
+    fn withdrawals_per_day() {
+        let input = "\
+type,client,tx,amount,timestamp
+deposit,1,1,500.0,0
+withdrawal,1,2,60.0,10
+withdrawal,1,3,50.0,20
+withdrawal,1,4,10.0,30
+withdrawal,1,5,10.0,40
+withdrawal,1,6,10.0,86411";
+
+        let output = run_with_limits(&["-"], input);
+
+        let records = parse_output(&output);
+        assert_eq!(records[0].available, dec("420.0000"));
+    }
+
+    /// Withdrawals with earlier timestamps than the ones before them still drop out once
+    /// they're out of the window.
+    #[test]
+    fn out_of_order_timestamps() {
+        let input = "\
+type,client,tx,amount,timestamp
+deposit,1,1,500.0,0
+withdrawal,1,2,10.0,100000
+withdrawal,1,3,10.0,10
+withdrawal,1,4,10.0,100020";
+
+        let output = run_with_limits(&["-"], input);
+
+        let records = parse_output(&output);
+        assert_eq!(records[0].available, dec("470.0000"));
+    }
+
+    #[test]
+    fn rejected_with_velocity_reason() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,5.0";
+
+        let stdout = run_with_limits(&["history", "--client", "1", "-"], input);
+
+        assert!(
+            stdout.contains("velocity limit reached (max 1 deposits per 3 rows)"),
+            "stdout: {stdout}"
+        );
+    }
+
+    #[test]
+    fn limit_without_window_rejected() {
+        let limits = temp_file("[[limit]]\noperation = \"deposit\"\nmax_count = 1\n");
+        let output = run_command(
+            &["--velocity-limits", limits.path().to_str().unwrap(), "-"],
+            "type,client,tx,amount\n",
+        );
+
+        assert!(!output.status.success());
+    }
+}
+
+// =============================================================================
+// 30. Fraud Alert Tests
+// =============================================================================
+
+mod fraud_alerts {
+    use super::*;
+
+    /// Runs the engine writing alerts, with the given extra arguments, and returns the
+    /// balances and the alerts file.
+    fn run_with_alerts(args: &[&str], input: &str) -> (Vec<ClientRecord>, String) {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("alerts.csv");
+        let mut all_args = vec!["--alerts", path.to_str().unwrap()];
+        all_args.extend_from_slice(args);
+        all_args.push("-");
+        let output = run_engine_with_args(&all_args, input);
+        let alerts = std::fs::read_to_string(&path).expect("Failed to read alerts");
+        (output, alerts)
+    }
+
+    #[test]
+    fn quick_withdrawal_then_dispute() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,100.0
+withdrawal,1,2,60.0
+withdrawal,1,3,40.0
+dispute,1,1,";
+
+        let (output, alerts) = run_with_alerts(&[], input);
+
+        assert_eq!(
+            alerts,
+            "line,client,rule,txs\n5,1,quick-withdrawal-dispute,1 2 3\n"
+        );
+        // Alerts don't block the dispute.
+        assert_eq!(output[0].held, dec("100.0000"));
+    }
+
+    /// A withdrawal only counts towards the deposits it used up, oldest first.
+    #[test]
+    fn withdrawals_allocated_first_in_first_out() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,100.0
+deposit,1,2,100.0
+withdrawal,1,3,100.0
+dispute,1,2,
+resolve,1,2,
+dispute,1,1,";
+
+        let (_, alerts) = run_with_alerts(&[], input);
+
+        assert_eq!(
+            alerts,
+            "line,client,rule,txs\n7,1,quick-withdrawal-dispute,1 3\n"
+        );
+    }
+
+    #[test]
+    fn structuring_just_under_threshold() {
+        let input = "\
+type,client,tx,amount
+deposit,2,4,9500
+deposit,2,5,9999
+deposit,2,6,8000
+deposit,2,7,9000";
+
+        let (_, alerts) = run_with_alerts(&[], input);
+
+        assert_eq!(alerts, "line,client,rule,txs\n5,2,structuring,4 5 7\n");
+    }
+
+    #[test]
+    fn repeated_dispute_cycles() {
+        let input = "\
+type,client,tx,amount
+deposit,3,8,10
+dispute,3,8,
+resolve,3,8,
+dispute,3,8,
+resolve,3,8,";
+
+        let (_, alerts) = run_with_alerts(&[], input);
+
+        assert_eq!(alerts, "line,client,rule,txs\n6,3,dispute-cycles,8\n");
+    }
+
+    #[test]
+    fn rules_from_file() {
+        let rules = temp_file(
+            "[quick_withdrawal]\nenabled = false\n\n[structuring]\nthreshold = \"100\"\nmin_count = 2\n",
+        );
+        let input = "\
+type,client,tx,amount
+deposit,1,1,95
+withdrawal,1,2,95
+deposit,1,3,99
+dispute,1,1,";
+
+        let (_, alerts) =
+            run_with_alerts(&["--alert-rules", rules.path().to_str().unwrap()], input);
+
+        assert_eq!(alerts, "line,client,rule,txs\n4,1,structuring,1 3\n");
+    }
+
+    #[test]
+    fn invalid_rules_rejected() {
+        for rules in [
+            "[structuring]\nthreshold = \"70000000000000000000000000000\"\nmargin = \"-5\"\n",
+            "[structuring]\nmargin = \"1.5\"\n",
+            "[structuring]\nthreshold = \"-1\"\n",
+            "[structuring]\nmin_count = 0\n",
+            "[quick_withdrawal]\nwindow_rows = 0\n",
+        ] {
+            let rules_file = temp_file(rules);
+            let output = run_command(
+                &[
+                    "--alerts",
+                    "alerts.csv",
+                    "--alert-rules",
+                    rules_file.path().to_str().unwrap(),
+                    "-",
+                ],
+                "type,client,tx,amount\ndeposit,1,1,1.0\n",
+            );
+
+            assert!(!output.status.success(), "{rules}");
+            assert!(
+                String::from_utf8_lossy(&output.stderr).contains("invalid alert rules"),
+                "{rules}"
+            );
+        }
+    }
+
+    #[test]
+    fn alert_rules_require_alerts() {
+        let rules = temp_file("");
+        let output = run_command(
+            &["--alert-rules", rules.path().to_str().unwrap(), "-"],
+            "type,client,tx,amount\n",
+        );
+
+        assert!(!output.status.success());
+    }
+}
+
+// =============================================================================
+// 31. Client Access List Tests
+// =============================================================================
+
+mod client_access {
+    use super::*;
+
+    const INPUT: &str = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,2,2,20.0
+deposit,3,3,30.0
+withdrawal,2,4,5.0";
+
+    fn clients(output: &[ClientRecord]) -> Vec<u16> {
+        let mut clients: Vec<u16> = output.iter().map(|record| record.client).collect();
+        clients.sort_unstable();
+        clients
+    }
+
+    #[test]
+    fn blocklisted_clients_rejected() {
+        let blocklist = temp_file("client\n2\n");
+        let output = run_engine_with_args(
+            &["--blocklist", blocklist.path().to_str().unwrap(), "-"],
+            INPUT,
+        );
+
+        assert_eq!(clients(&output), vec![1, 3]);
+    }
+
+    #[test]
+    fn only_allowlisted_clients_accepted() {
+        let allowlist = temp_file("client\n2\n3\n");
+        let output = run_engine_with_args(
+            &["--allowlist", allowlist.path().to_str().unwrap(), "-"],
+            INPUT,
+        );
+
+        assert_eq!(clients(&output), vec![2, 3]);
+        let client_2 = output.iter().find(|record| record.client == 2).unwrap();
+        assert_eq!(client_2.available, dec("15.0000"));
+    }
+
+    /// A client on both lists is blocked.
+    #[test]
+    fn blocklist_wins_over_allowlist() {
+        let allowlist = temp_file("client\n2\n3\n");
+        let blocklist = temp_file("client\n3\n");
+        let output = run_engine_with_args(
+            &[
+                "--allowlist",
+                allowlist.path().to_str().unwrap(),
+                "--blocklist",
+                blocklist.path().to_str().unwrap(),
+                "-",
+            ],
+            INPUT,
+        );
+
+        assert_eq!(clients(&output), vec![2]);
+    }
+
+    #[test]
+    fn blocked_counted_in_metadata() {
+        let blocklist = temp_file("client\n2\n");
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("metadata.json");
+        run_engine_with_args(
+            &[
+                "--blocklist",
+                blocklist.path().to_str().unwrap(),
+                "--metadata",
+                path.to_str().unwrap(),
+                "-",
+            ],
+            &format!("{INPUT}\nwithdrawal,1,5,100.0"),
+        );
+
+        let content = std::fs::read_to_string(&path).expect("Failed to read metadata");
+        let metadata: serde_json::Value = serde_json::from_str(&content).expect("Invalid JSON");
+        assert_eq!(metadata["rows"], 5);
+        assert_eq!(metadata["rejected"], 1);
+        assert_eq!(metadata["blocked"], 2);
+    }
+}
+
+// =============================================================================
+// 32. Multi-Currency Tests
+// =============================================================================
+
+mod currencies {
+    use super::*;
+
+    /// Runs the engine on an input with a currency column and returns the raw output lines,
+    /// sorted by client and currency.
+    fn run_with_currencies(input: &str) -> Vec<String> {
+        sorted_rows(&run_ok(&["-"], input))
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn balances_per_client_and_currency() {
+        let output = run_with_currencies(
+            "type,client,tx,amount,currency
+deposit,1,1,10.0,USD
+deposit,1,2,5.0,EUR
+withdrawal,1,3,3.0,USD
+deposit,2,4,1.0,EUR",
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,EUR,5.0000,0.0000,5.0000,false",
+                "1,USD,7.0000,0.0000,7.0000,false",
+                "2,EUR,1.0000,0.0000,1.0000,false",
+            ]
+        );
+    }
+
+    /// Withdrawals only draw on the balance in their own currency.
+    #[test]
+    fn withdrawal_rejected_without_funds_in_currency() {
+        let output = run_with_currencies(
+            "type,client,tx,amount,currency
+deposit,1,1,10.0,USD
+withdrawal,1,2,3.0,EUR",
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,USD,10.0000,0.0000,10.0000,false",
+            ]
+        );
+    }
+
+    /// Disputes don't need a currency: they apply to the deposit's.
+    #[test]
+    fn dispute_uses_deposit_currency() {
+        let output = run_with_currencies(
+            "type,client,tx,amount,currency
+deposit,1,1,10.0,USD
+deposit,1,2,5.0,EUR
+dispute,1,2,,",
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,EUR,0.0000,5.0000,5.0000,false",
+                "1,USD,10.0000,0.0000,10.0000,false",
+            ]
+        );
+    }
+
+    /// A client's transaction ids are unique across currencies, so a dispute without a
+    /// currency can only refer to one deposit.
+    #[test]
+    fn duplicate_tx_in_other_currency_rejected() {
+        let output = run_with_currencies(
+            "type,client,tx,amount,currency
+deposit,1,1,10.0,USD
+deposit,1,1,5.0,EUR
+dispute,1,1,,",
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,USD,0.0000,10.0000,10.0000,false",
+            ]
+        );
+    }
+
+    #[test]
+    fn deposit_without_currency_rejected() {
+        let output = run_with_currencies(
+            "type,client,tx,amount,currency
+deposit,1,1,10.0,USD
+deposit,1,2,5.0,",
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,USD,10.0000,0.0000,10.0000,false",
+            ]
+        );
+    }
+
+    /// A chargeback locks the client's accounts in every currency.
+    #[test]
+    fn chargeback_locks_all_currencies() {
+        let output = run_with_currencies(
+            "type,client,tx,amount,currency
+deposit,1,1,10.0,USD
+deposit,1,2,5.0,EUR
+dispute,1,1,,
+chargeback,1,1,,
+deposit,1,3,1.0,EUR
+deposit,1,4,1.0,GBP",
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,EUR,5.0000,0.0000,5.0000,true",
+                "1,USD,0.0000,0.0000,0.0000,true",
+            ]
+        );
+    }
+
+    /// Inputs without a currency column keep a single balance per client.
+    #[test]
+    fn input_without_currency_column() {
+        let output = run_command(&["-"], "type,client,tx,amount\ndeposit,1,1,10.0");
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn verify_not_supported() {
+        let output = run_command(
+            &["--verify", "-"],
+            "type,client,tx,amount,currency\ndeposit,1,1,10.0,USD",
+        );
+
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("--verify doesn't support inputs with a currency column")
+        );
+    }
+
+    /// Reports and commands that would mix the balances of several currencies fail.
+    #[test]
+    fn single_currency_reports_not_supported() {
+        let input = "type,client,tx,amount,currency\ndeposit,1,1,10.0,USD";
+        for (args, option) in [
+            (
+                &["--open-disputes", "disputes.csv", "-"][..],
+                "--open-disputes",
+            ),
+            (&["--risk-report", "risk.csv", "-"][..], "--risk-report"),
+            (&["history", "--client", "1", "-"][..], "history"),
+            (&["explain", "--client", "1", "-"][..], "explain"),
+        ] {
+            let output = run_command(args, input);
+
+            assert!(!output.status.success(), "{option}");
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(
+                stderr.contains(&format!(
+                    "{option} doesn't support inputs with a currency column"
+                )),
+                "stderr: {stderr}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_currency_rejected() {
+        let output = run_with_currencies(
+            "type,client,tx,amount,currency
+deposit,1,1,10.0,usd
+deposit,1,2,5.0,EUR",
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,EUR,5.0000,0.0000,5.0000,false",
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_by_client_and_currency() {
+        let left = temp_file(
+            "client,currency,available,held,total,locked\n1,USD,10,0,10,false\n1,EUR,5,0,5,false\n",
+        );
+        let right = temp_file(
+            "client,currency,available,held,total,locked\n1,EUR,5,0,5,false\n1,GBP,1,0,1,false\n",
+        );
+        let output = run_command(
+            &[
+                "diff",
+                left.path().to_str().unwrap(),
+                right.path().to_str().unwrap(),
+            ],
+            "",
+        );
+
+        assert!(!output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "- client 1 USD\n+ client 1 GBP\n2 differences.\n"
+        );
+    }
+}
+
+// =============================================================================
+// 33. Consolidated Report Tests
+// =============================================================================
+
+mod consolidated {
+    use super::*;
+
+    const INPUT: &str = "\
+type,client,tx,amount,currency,timestamp
+deposit,1,1,10.0,USD,100
+deposit,1,2,5.0,EUR,200
+deposit,2,3,3.0,GBP,300
+dispute,1,2,,,400";
+
+    const RATES: &str = "\
+from,to,rate,effective
+EUR,USD,1.1,
+EUR,USD,1.2,350
+EUR,USD,1.3,500
+USD,GBP,0.75,
+";
+
+    /// Runs the engine with a consolidated report in `currency` and returns the report.
+    fn run_consolidated(extra_args: &[&str], currency: &str, input: &str) -> String {
+        let rates = temp_file(RATES);
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("consolidated.csv");
+        let mut args = vec![
+            "--consolidated",
+            path.to_str().unwrap(),
+            "--rates",
+            rates.path().to_str().unwrap(),
+            "--reporting-currency",
+            currency,
+        ];
+        args.extend_from_slice(extra_args);
+        args.push("-");
+        run_ok(&args, input);
+        std::fs::read_to_string(&path).expect("Failed to read consolidated report")
+    }
+
+    /// Uses the rates effective at the last timestamp, and inverse rates for pairs without
+    /// their own.
+    #[test]
+    fn converts_at_rates_of_last_timestamp() {
+        let report = run_consolidated(&[], "USD", INPUT);
+
+        assert_eq!(
+            report,
+            "client,currency,available,held,total,locked\n\
+             1,USD,10.0000,6.0000,16.0000,false\n\
+             2,USD,4.0000,0.0000,4.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn balances_unchanged() {
+        let rates = temp_file(RATES);
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("consolidated.csv");
+        let output = sorted_rows(&run_ok(
+            &[
+                "--consolidated",
+                path.to_str().unwrap(),
+                "--rates",
+                rates.path().to_str().unwrap(),
+                "--reporting-currency",
+                "USD",
+                "-",
+            ],
+            INPUT,
+        ));
+
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,EUR,0.0000,5.0000,5.0000,false",
+                "1,USD,10.0000,0.0000,10.0000,false",
+                "2,GBP,3.0000,0.0000,3.0000,false",
+            ]
+        );
+    }
+
+    /// Amounts are summed at full precision and only the sums are rounded.
+    #[test]
+    fn rounding_strategy() {
+        let input = "type,client,tx,amount,currency\n\
+                     deposit,1,1,1.0,USD\n\
+                     deposit,1,2,1.0,GBP";
+
+        assert_eq!(
+            run_consolidated(&[], "GBP", input),
+            "client,currency,available,held,total,locked\n1,GBP,1.7500,0.0000,1.7500,false\n"
+        );
+        // 1 GBP is 1.3333... USD.
+        assert_eq!(
+            run_consolidated(&["--consolidated-rounding", "up"], "USD", input),
+            "client,currency,available,held,total,locked\n1,USD,2.3334,0.0000,2.3334,false\n"
+        );
+        assert_eq!(
+            run_consolidated(&["--consolidated-rounding", "down"], "USD", input),
+            "client,currency,available,held,total,locked\n1,USD,2.3333,0.0000,2.3333,false\n"
+        );
+    }
+
+    #[test]
+    fn missing_rate_fails() {
+        let rates = temp_file(RATES);
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("consolidated.csv");
+        let output = run_command(
+            &[
+                "--consolidated",
+                path.to_str().unwrap(),
+                "--rates",
+                rates.path().to_str().unwrap(),
+                "--reporting-currency",
+                "GBP",
+                "-",
+            ],
+            INPUT,
+        );
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("no rate from EUR to GBP"));
+    }
+
+    #[test]
+    fn requires_currency_column() {
+        let rates = temp_file(RATES);
+        let output = run_command(
+            &[
+                "--consolidated",
+                "consolidated.csv",
+                "--rates",
+                rates.path().to_str().unwrap(),
+                "--reporting-currency",
+                "USD",
+                "-",
+            ],
+            "type,client,tx,amount\ndeposit,1,1,10.0",
+        );
+
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn rates_require_consolidated() {
+        let rates = temp_file(RATES);
+        let output = run_command(
+            &["--rates", rates.path().to_str().unwrap(), "-"],
+            "type,client,tx,amount,currency\n",
+        );
+
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn invalid_rate_rejected() {
+        let rates = temp_file("from,to,rate\nEUR,USD,-1\n");
+        let output = run_command(
+            &[
+                "--consolidated",
+                "consolidated.csv",
+                "--rates",
+                rates.path().to_str().unwrap(),
+                "--reporting-currency",
+                "USD",
+                "-",
+            ],
+            "type,client,tx,amount,currency\n",
+        );
+
+        assert!(!output.status.success());
+    }
+}
+
+// =============================================================================
+// 34. Transfer Tests
+// =============================================================================
+
+mod transfers {
+    use super::*;
+
+    fn client_record(
+        client: u16,
+        available: &str,
+        held: &str,
+        total: &str,
+        locked: bool,
+    ) -> ClientRecord {
+        ClientRecord {
+            client,
+            available: dec(available),
+            held: dec(held),
+            total: dec(total),
+            locked,
+        }
+    }
+
+    const FUNDED: &str = "\
+type,client,tx,amount,destination
+deposit,1,1,10.0,
+deposit,2,2,5.0,";
+
+    #[test]
+    fn transfer_moves_available_funds() {
+        let output = run_engine(&format!("{FUNDED}\ntransfer,1,3,4.0,2"));
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "6.0000", "0.0000", "6.0000", false),
+                client_record(2, "9.0000", "0.0000", "9.0000", false),
+            ],
+        );
+    }
+
+    /// Like a withdrawal fee, a transfer can't open the destination's account when only
+    /// deposits open accounts.
+    #[test]
+    fn unknown_destination_rejected() {
+        let output = run_engine(&format!("{FUNDED}\ntransfer,1,3,4.0,3"));
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "10.0000", "0.0000", "10.0000", false),
+                client_record(2, "5.0000", "0.0000", "5.0000", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn transfer_opens_destination_account() {
+        let policy = temp_file("only_deposits_create_accounts = false\n");
+        let output = run_engine_with_args(
+            &["--policy", policy.path().to_str().unwrap(), "-"],
+            &format!("{FUNDED}\ntransfer,1,3,4.0,3"),
+        );
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "6.0000", "0.0000", "6.0000", false),
+                client_record(2, "5.0000", "0.0000", "5.0000", false),
+                client_record(3, "4.0000", "0.0000", "4.0000", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn insufficient_funds_rejects_both_sides() {
+        let output = run_engine(&format!("{FUNDED}\ntransfer,1,3,10.5,2"));
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "10.0000", "0.0000", "10.0000", false),
+                client_record(2, "5.0000", "0.0000", "5.0000", false),
+            ],
+        );
+    }
+
+    /// Held funds can't be transferred.
+    #[test]
+    fn held_funds_not_transferable() {
+        let output = run_engine(&format!("{FUNDED}\ndispute,1,1,,\ntransfer,1,3,1.0,2"));
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "0.0000", "10.0000", "10.0000", false),
+                client_record(2, "5.0000", "0.0000", "5.0000", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn locked_source_rejected() {
+        let output = run_engine(&format!(
+            "{FUNDED}\ndeposit,1,3,1.0,\ndispute,1,1,,\nchargeback,1,1,,\ntransfer,1,4,1.0,2"
+        ));
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "1.0000", "0.0000", "1.0000", true),
+                client_record(2, "5.0000", "0.0000", "5.0000", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn locked_destination_rejected() {
+        let output = run_engine(&format!(
+            "{FUNDED}\ndispute,2,2,,\nchargeback,2,2,,\ntransfer,1,3,4.0,2"
+        ));
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "10.0000", "0.0000", "10.0000", false),
+                client_record(2, "0.0000", "0.0000", "0.0000", true),
+            ],
+        );
+    }
+
+    #[test]
+    fn balance_limit_on_destination() {
+        let output = run_engine_with_args(
+            &["--max-balance", "10", "-"],
+            &format!("{FUNDED}\ntransfer,1,3,4.0,2\ntransfer,1,4,3.0,2"),
+        );
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "6.0000", "0.0000", "6.0000", false),
+                client_record(2, "9.0000", "0.0000", "9.0000", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_transfers_rejected() {
+        let output = run_engine(&format!(
+            "{FUNDED}\ntransfer,1,3,1.0,\ntransfer,1,4,1.0,1\ntransfer,1,5,,2\ntransfer,3,6,1.0,1"
+        ));
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "10.0000", "0.0000", "10.0000", false),
+                client_record(2, "5.0000", "0.0000", "5.0000", false),
+            ],
+        );
+    }
+
+    #[test]
+    fn blocked_destination_rejected() {
+        let blocklist = temp_file("client\n2\n");
+        let output = run_engine_with_args(
+            &["--blocklist", blocklist.path().to_str().unwrap(), "-"],
+            &format!("{FUNDED}\ntransfer,1,3,4.0,2"),
+        );
+
+        assert_records_eq(
+            output,
+            vec![client_record(1, "10.0000", "0.0000", "10.0000", false)],
+        );
+    }
+
+    #[test]
+    fn transfers_verified_and_journaled() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("journal.csv");
+        run_engine_with_args(
+            &["--verify", "--journal", path.to_str().unwrap(), "-"],
+            &format!("{FUNDED}\ntransfer,1,3,4.0,2\ntransfer,2,4,9.0,1"),
+        );
+
+        let journal = std::fs::read_to_string(&path).expect("Failed to read journal");
+        let transfers: Vec<&str> = journal
+            .lines()
+            .filter(|line| line.contains(",transfer,"))
+            .collect();
+        assert_eq!(
+            transfers,
+            vec![
+                "4,3,transfer,client:1:available,4.0000,",
+                "4,3,transfer,client:2:available,,4.0000",
+                "5,4,transfer,client:2:available,9.0000,",
+                "5,4,transfer,client:1:available,,9.0000",
+            ]
+        );
+    }
+
+    /// Like a deposit, a transfer pays off the destination's debt before adding to its
+    /// available balance.
+    #[test]
+    fn transfer_pays_off_destination_debt() {
+        let policy = temp_file("dispute_shortfall = \"debt\"\n");
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("journal.csv");
+        let output = run_ok(
+            &[
+                "--policy",
+                policy.path().to_str().unwrap(),
+                "--verify",
+                "--journal",
+                path.to_str().unwrap(),
+                "-",
+            ],
+            "type,client,tx,amount,destination
+deposit,1,1,50.0,
+deposit,2,2,100.0,
+withdrawal,2,3,100.0,
+dispute,2,2,,
+transfer,1,4,10.0,2",
+        );
+
+        assert_eq!(
+            sorted_rows(&output).lines().collect::<Vec<_>>(),
+            vec![
+                "client,available,held,total,locked,debt",
+                "1,40.0000,0.0000,40.0000,false,0.0000",
+                "2,0.0000,0.0000,0.0000,false,90.0000",
+            ]
+        );
+        let journal = std::fs::read_to_string(&path).expect("Failed to read journal");
+        let transfers: Vec<&str> = journal
+            .lines()
+            .filter(|line| line.contains(",transfer,"))
+            .collect();
+        assert_eq!(
+            transfers,
+            vec![
+                "6,4,transfer,client:1:available,10.0000,",
+                "6,4,transfer,client:2:debt,,10.0000",
+            ]
+        );
+    }
+
+    /// The destination's side of a transfer shows up in events, in its history and in its
+    /// explanation.
+    #[test]
+    fn destination_in_events_and_history() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let events = dir.path().join("events.jsonl");
+        let input = format!("{FUNDED}\ntransfer,1,3,4.0,2");
+        run_ok(&["--events", events.to_str().unwrap(), "-"], &input);
+
+        let content = std::fs::read_to_string(&events).expect("Failed to read events");
+        let transfers: Vec<serde_json::Value> = content
+            .lines()
+            .skip(2)
+            .map(|line| serde_json::from_str(line).expect("Invalid JSON"))
+            .collect();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0]["client"], 1);
+        assert_eq!(transfers[0]["after"]["available"], "6.0000");
+        assert_eq!(transfers[1]["client"], 2);
+        assert_eq!(transfers[1]["operation"], "transfer");
+        assert_eq!(transfers[1]["before"]["available"], "5.0000");
+        assert_eq!(transfers[1]["after"]["available"], "9.0000");
+
+        assert_eq!(
+            run_ok(&["history", "--client", "2", "-"], &input),
+            "line,type,tx,amount,status,reason,available,held,total,locked\n\
+             3,deposit,2,5.0000,applied,,5.0000,0.0000,5.0000,false\n\
+             4,transfer,3,4.0000,applied,,9.0000,0.0000,9.0000,false\n"
+        );
+        let explanation = run_ok(&["explain", "--client", "2", "-"], &input);
+        assert!(
+            explanation.contains("line 4: transfer 4.0000 from client 1 (tx 3) was applied"),
+            "stdout: {explanation}"
+        );
+    }
+
+    /// Transfers take money out of the source's account, so withdrawal velocity limits count
+    /// them.
+    #[test]
+    fn transfers_count_towards_withdrawal_limits() {
+        let limits =
+            temp_file("[[limit]]\noperation = \"withdrawal\"\nwindow_rows = 10\nmax_count = 1\n");
+        let output = run_engine_with_args(
+            &["--velocity-limits", limits.path().to_str().unwrap(), "-"],
+            &format!("{FUNDED}\ntransfer,1,3,4.0,2\nwithdrawal,1,4,1.0,"),
+        );
+
+        assert_records_eq(
+            output,
+            vec![
+                client_record(1, "6.0000", "0.0000", "6.0000", false),
+                client_record(2, "9.0000", "0.0000", "9.0000", false),
+            ],
+        );
+    }
+
+    /// A deposit moved to another client and then disputed is as suspicious as one withdrawn.
+    #[test]
+    fn transfer_counts_as_quick_withdrawal() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("alerts.csv");
+        run_ok(
+            &["--alerts", path.to_str().unwrap(), "-"],
+            &format!("{FUNDED}\ntransfer,1,3,10.0,2\ndispute,1,1,,"),
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("Failed to read alerts"),
+            "line,client,rule,txs\n5,1,quick-withdrawal-dispute,1 3\n"
+        );
+    }
+
+    #[test]
+    fn transfer_within_currency() {
+        let output = sorted_rows(&run_ok(
+            &["-"],
+            "type,client,tx,amount,currency,destination
+deposit,1,1,10.0,USD,
+deposit,1,2,10.0,EUR,
+deposit,2,5,1.0,USD,
+transfer,1,3,4.0,USD,2
+transfer,1,4,11.0,EUR,2",
+        ));
+
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec![
+                "client,currency,available,held,total,locked",
+                "1,EUR,10.0000,0.0000,10.0000,false",
+                "1,USD,6.0000,0.0000,6.0000,false",
+                "2,USD,5.0000,0.0000,5.0000,false",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_reports_bad_destinations() {
+        let output = run_command(
+            &["validate", "-"],
+            &format!("{FUNDED}\ntransfer,1,3,1.0,\ntransfer,1,4,1.0,1\ntransfer,1,5,1.0,2"),
+        );
+
+        assert!(!output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("destination (2):"), "{stdout}");
+    }
+}
+
 // =============================================================================
 // File-based Test (from specification PDF example)
 // =============================================================================